@@ -75,14 +75,14 @@ export default function GamePage() {
     return () => clearInterval(interval);
   }, [view, currentGameId, refreshGameState]);
 
-  const handleCreateGame = async (gameId: number, stake: number) => {
+  const handleCreateGame = async (stake: number) => {
     if (!wallet.publicKey) return;
     setLoading(true);
     setError(null);
     try {
       const provider = getProvider();
       const program = getProgram(provider);
-      await createGame(program, gameId, stake, wallet.publicKey);
+      const { gameId } = await createGame(program, stake, wallet.publicKey);
 
       // Auto-join after creating
       await joinGame(program, gameId, wallet.publicKey);
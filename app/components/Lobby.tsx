@@ -1,9 +1,9 @@
 "use client";
 
-import { useState, useEffect } from "react";
+import { useState } from "react";
 
 interface LobbyProps {
-  onCreateGame: (gameId: number, stake: number) => Promise<void>;
+  onCreateGame: (stake: number) => Promise<void>;
   onJoinGame: (gameId: number) => Promise<void>;
   onStartGame: (gameId: number) => Promise<void>;
   isConnected: boolean;
@@ -17,12 +17,8 @@ export default function Lobby({
   isConnected,
   loading,
 }: LobbyProps) {
-  const [gameId, setGameId] = useState(1);
-
-  // Generate random game ID only on client to avoid hydration mismatch
-  useEffect(() => {
-    setGameId(Math.floor(Math.random() * 100000));
-  }, []);
+  // Game IDs are assigned by the program; this one is only for starting a game created earlier
+  const [gameId, setGameId] = useState("");
   const [joinGameId, setJoinGameId] = useState("");
   const [stake, setStake] = useState(0);
   const [tab, setTab] = useState<"create" | "join">("create");
@@ -63,15 +59,6 @@ export default function Lobby({
 
           {tab === "create" ? (
             <>
-              <div>
-                <label className="block text-sm font-medium text-gray-400 mb-1">Game ID</label>
-                <input
-                  type="number"
-                  value={gameId}
-                  onChange={(e) => setGameId(Number(e.target.value))}
-                  className="w-full bg-gray-900 text-white rounded-lg px-4 py-2 border border-gray-600 focus:border-blue-500 focus:outline-none"
-                />
-              </div>
               <div>
                 <label className="block text-sm font-medium text-gray-400 mb-1">
                   Stake (SOL) — 0 for free game
@@ -85,15 +72,25 @@ export default function Lobby({
                 />
               </div>
               <button
-                onClick={() => onCreateGame(gameId, Math.floor(stake * 1_000_000_000))}
+                onClick={() => onCreateGame(Math.floor(stake * 1_000_000_000))}
                 disabled={!isConnected || loading}
                 className="w-full bg-blue-600 hover:bg-blue-500 disabled:bg-gray-600 disabled:cursor-not-allowed text-white font-bold py-3 rounded-lg transition"
               >
                 {loading ? "Creating..." : "🚀 Create Game"}
               </button>
+              <div>
+                <label className="block text-sm font-medium text-gray-400 mb-1">Game ID to Start</label>
+                <input
+                  type="number"
+                  value={gameId}
+                  onChange={(e) => setGameId(e.target.value)}
+                  placeholder="Enter the ID of a game you created..."
+                  className="w-full bg-gray-900 text-white rounded-lg px-4 py-2 border border-gray-600 focus:border-blue-500 focus:outline-none"
+                />
+              </div>
               <button
-                onClick={() => onStartGame(gameId)}
-                disabled={!isConnected || loading}
+                onClick={() => onStartGame(Number(gameId))}
+                disabled={!isConnected || loading || !gameId}
                 className="w-full bg-purple-600 hover:bg-purple-500 disabled:bg-gray-600 disabled:cursor-not-allowed text-white font-bold py-3 rounded-lg transition"
               >
                 {loading ? "Starting..." : "▶️ Start Game"}
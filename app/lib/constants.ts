@@ -22,3 +22,10 @@ export const PLAYER_EMOJIS = ["🔵", "🔴", "🟢", "🟡"];
 
 export const GAME_SEED = new TextEncoder().encode("game");
 export const PLAYER_SEED = new TextEncoder().encode("player");
+export const MARKET_SEED = new TextEncoder().encode("market");
+export const ACTION_LOG_SEED = new TextEncoder().encode("action_log");
+export const PROGRAM_CONFIG_SEED = new TextEncoder().encode("program_config");
+export const GAME_COUNTER_SEED = new TextEncoder().encode("game_counter");
+
+// The program's game counter assigns IDs from here up; create_game only takes IDs below it
+export const FIRST_ASSIGNED_GAME_ID = 2 ** 52;
@@ -1,6 +1,14 @@
 import { PublicKey } from "@solana/web3.js";
 import { BN } from "@coral-xyz/anchor";
-import { PROGRAM_ID, GAME_SEED, PLAYER_SEED } from "./constants";
+import {
+  PROGRAM_ID,
+  GAME_SEED,
+  PLAYER_SEED,
+  MARKET_SEED,
+  ACTION_LOG_SEED,
+  PROGRAM_CONFIG_SEED,
+  GAME_COUNTER_SEED,
+} from "./constants";
 
 /** Encode a number as 8-byte little-endian Uint8Array (browser-safe, no BigInt Buffer methods) */
 function encodeU64LE(value: number): Uint8Array {
@@ -23,6 +31,28 @@ export function getPlayerPDA(gameId: number, player: PublicKey): [PublicKey, num
   );
 }
 
+export function getMarketPDA(gameId: number): [PublicKey, number] {
+  return PublicKey.findProgramAddressSync([MARKET_SEED, encodeU64LE(gameId)], PROGRAM_ID);
+}
+
+export function getActionLogPDA(gameId: number): [PublicKey, number] {
+  return PublicKey.findProgramAddressSync([ACTION_LOG_SEED, encodeU64LE(gameId)], PROGRAM_ID);
+}
+
+export function getProgramConfigPDA(): [PublicKey, number] {
+  return PublicKey.findProgramAddressSync([PROGRAM_CONFIG_SEED], PROGRAM_ID);
+}
+
+export function getGameCounterPDA(): [PublicKey, number] {
+  return PublicKey.findProgramAddressSync([GAME_COUNTER_SEED], PROGRAM_ID);
+}
+
+/** Decode a u64 returned by an instruction (base64 return data) */
+export function decodeU64Return(data: string): number {
+  const bytes = Uint8Array.from(atob(data), (c) => c.charCodeAt(0));
+  return new BN(bytes, "le").toNumber();
+}
+
 export type TileState =
   | { kind: "empty" }
   | { kind: "owned"; player: number; units: number; hasDefense: boolean; hasMine: boolean }
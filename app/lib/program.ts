@@ -1,7 +1,20 @@
 import { PublicKey, SystemProgram, TransactionInstruction } from "@solana/web3.js";
 import { Program, AnchorProvider, BN } from "@coral-xyz/anchor";
 import IDL from "./ghostnet.json";
-import { getGamePDA, getPlayerPDA, parseGameAccount, parsePlayerAccount, GameState, PlayerState } from "./game";
+import { FIRST_ASSIGNED_GAME_ID } from "./constants";
+import {
+  getGamePDA,
+  getPlayerPDA,
+  getMarketPDA,
+  getActionLogPDA,
+  getProgramConfigPDA,
+  getGameCounterPDA,
+  decodeU64Return,
+  parseGameAccount,
+  parsePlayerAccount,
+  GameState,
+  PlayerState,
+} from "./game";
 
 /* eslint-disable @typescript-eslint/no-explicit-any */
 export type GhostnetProgram = Program<any>;
@@ -19,19 +32,95 @@ function account(program: GhostnetProgram): any {
   return (program as any).account;
 }
 
+/** Team layout, victory condition and mode, as Anchor enum objects */
+export interface GameRules {
+  teamLayout: any;
+  victoryCondition: any;
+  gameMode: any;
+}
+
+export const DEFAULT_RULES: GameRules = {
+  teamLayout: { freeForAll: {} },
+  victoryCondition: { conquest: {} },
+  gameMode: { standard: {} },
+};
+
+/**
+ * Optional accounts the web app never uses. The action log is passed only when the game has one,
+ * since the program then requires it.
+ */
+async function gameplayAccounts(program: GhostnetProgram, gameId: number) {
+  const [gamePDA] = getGamePDA(gameId);
+  const game = await account(program).game.fetch(gamePDA);
+  return {
+    sessionToken: null,
+    agentAuthority: null,
+    actionLog: game.actionLog ? getActionLogPDA(gameId)[0] : null,
+  };
+}
+
+/**
+ * Create a game under the next ID from the program's game counter and return that ID.
+ * The game PDA is derived from the counter just before sending; if another game takes the ID
+ * first the transaction fails on the seeds check and can be retried.
+ */
 export async function createGame(
+  program: GhostnetProgram,
+  stakeAmount: number,
+  creator: PublicKey,
+  rules: GameRules = DEFAULT_RULES
+) {
+  const [counterPDA] = getGameCounterPDA();
+  const counter = await account(program).gameCounter.fetch(counterPDA);
+  const [gamePDA] = getGamePDA(counter.nextId.toNumber());
+
+  const tx = await methods(program)
+    .createNextGame(new BN(stakeAmount), rules.teamLayout, rules.victoryCondition, rules.gameMode)
+    .accounts({
+      gameCounter: counterPDA,
+      game: gamePDA,
+      creator: creator,
+      programConfig: getProgramConfigPDA()[0],
+      gameConfig: null,
+      creatorProfile: null,
+      systemProgram: SystemProgram.programId,
+    })
+    .rpc({ commitment: "confirmed" });
+
+  // The assigned ID is the instruction's return data
+  const confirmed = await program.provider.connection.getTransaction(tx, {
+    commitment: "confirmed",
+    maxSupportedTransactionVersion: 0,
+  });
+  const returnData = confirmed?.meta?.returnData;
+  if (!returnData) {
+    throw new Error(`create_next_game returned no game ID (tx ${tx})`);
+  }
+  const gameId = decodeU64Return(returnData.data[0]);
+
+  return { tx, gameId, gamePDA };
+}
+
+/** Create a game under a caller-chosen ID, which must be below FIRST_ASSIGNED_GAME_ID */
+export async function createGameWithId(
   program: GhostnetProgram,
   gameId: number,
   stakeAmount: number,
-  creator: PublicKey
+  creator: PublicKey,
+  rules: GameRules = DEFAULT_RULES
 ) {
+  if (!Number.isSafeInteger(gameId) || gameId < 0 || gameId >= FIRST_ASSIGNED_GAME_ID) {
+    throw new Error(`Game ID must be an integer from 0 to ${FIRST_ASSIGNED_GAME_ID - 1}`);
+  }
   const [gamePDA] = getGamePDA(gameId);
 
   const tx = await methods(program)
-    .createGame(new BN(gameId), new BN(stakeAmount))
+    .createGame(new BN(gameId), new BN(stakeAmount), rules.teamLayout, rules.victoryCondition, rules.gameMode)
     .accounts({
       game: gamePDA,
       creator: creator,
+      programConfig: getProgramConfigPDA()[0],
+      gameConfig: null,
       systemProgram: SystemProgram.programId,
     })
     .rpc();
@@ -53,6 +142,7 @@ export async function joinGame(
       game: gamePDA,
       playerState: playerPDA,
       player: player,
+      programConfig: getProgramConfigPDA()[0],
       systemProgram: SystemProgram.programId,
     })
     .rpc();
@@ -71,7 +161,9 @@ export async function startGame(
     .startGame()
     .accounts({
       game: gamePDA,
+      market: getMarketPDA(gameId)[0],
       creator: creator,
+      systemProgram: SystemProgram.programId,
     })
     .rpc();
 
@@ -97,6 +189,7 @@ export async function moveUnits(
       game: gamePDA,
       playerState: playerPDA,
       player: player,
+      ...(await gameplayAccounts(program, gameId)),
     })
     .rpc();
 
@@ -119,6 +212,7 @@ export async function buildDefense(
       game: gamePDA,
       playerState: playerPDA,
       player: player,
+      ...(await gameplayAccounts(program, gameId)),
     })
     .rpc();
 
@@ -142,6 +236,7 @@ export async function trainUnits(
       game: gamePDA,
       playerState: playerPDA,
       player: player,
+      ...(await gameplayAccounts(program, gameId)),
     })
     .rpc();
 
@@ -162,6 +257,7 @@ export async function collectResources(
       game: gamePDA,
       playerState: playerPDA,
       player: player,
+      ...(await gameplayAccounts(program, gameId)),
     })
     .rpc();
 
@@ -187,6 +283,7 @@ export async function setStrategy(
     .accounts({
       playerState: playerPDA,
       player: player,
+      sessionToken: null,
     })
     .rpc();
 
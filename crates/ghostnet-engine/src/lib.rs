@@ -0,0 +1,1709 @@
+//! Ghostnet Territories rules with no Solana or Anchor dependencies.
+//!
+//! The on-chain program, bots, simulators and replay verifiers all drive the
+//! same [`GameState::apply`]. Build with `default-features = false` for `no_std`.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use core::cmp::Reverse;
+use core::fmt;
+
+// ==================== CONSTANTS ====================
+
+pub const GRID_SIZE: u8 = 8;
+pub const MAX_PLAYERS: u8 = 4;
+pub const MAX_UNITS: u8 = 20;
+pub const INITIAL_GOLD: u64 = 100;
+pub const INITIAL_WOOD: u64 = 50;
+pub const INITIAL_UNITS: u8 = 3;
+pub const UNIT_COST_GOLD: u64 = 25;
+pub const DEFENSE_COST_WOOD: u64 = 30;
+pub const RESOURCE_PER_TICK: u64 = 5;
+/// Extra defenders a fortified tile counts as
+pub const DEFENSE_BONUS: u16 = 2;
+pub const CLAIM_SCORE: u64 = 10;
+pub const CAPTURE_SCORE: u64 = 50;
+pub const RESOURCE_SCORE: u64 = 100;
+pub const DEFENSE_SCORE: u64 = 20;
+/// Storm stops once only the center 2x2 remains
+pub const MAX_STORM_RINGS: u8 = GRID_SIZE / 2 - 1;
+pub const CTF_CAPTURE_SCORE: u64 = 500;
+pub const CAMP_UNITS: u8 = 3;
+pub const CAMP_CAPTURE_SCORE: u64 = 75;
+/// Neutral camps placed at start, as (x, y, reward); rotationally symmetric
+pub const NEUTRAL_CAMPS: [(usize, usize, CampReward); 4] = [
+    (3, 1, CampReward::Gold { amount: 150 }),
+    (6, 3, CampReward::Wood { amount: 150 }),
+    (4, 6, CampReward::Gold { amount: 150 }),
+    (1, 4, CampReward::Wood { amount: 150 }),
+];
+/// Center resource tiles contested in King of the Hill, as (x, y)
+pub const CENTER_TILES: [(usize, usize); 4] = [(3, 3), (4, 4), (4, 3), (3, 4)];
+pub const BARBARIAN_BASE_STRENGTH: u8 = 3;
+pub const BARBARIAN_REWARD_GOLD: u64 = 100;
+pub const MIN_RISK_THRESHOLD: u16 = 100;
+pub const MAX_RISK_THRESHOLD: u16 = 1000;
+
+// ==================== STATE ====================
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Player {
+    pub team: u8,
+    pub gold: u64,
+    pub wood: u64,
+    pub units: u8,
+    pub score: u64,
+    pub is_alive: bool,
+}
+
+/// Everything the rules read and write. Hosts only need to fill in the
+/// players an action touches; the others can stay at their defaults.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct GameState {
+    pub status: GameStatus,
+    pub player_count: u8,
+    pub turn: u64,
+    /// Unix timestamp actions are applied at, set by the host
+    pub now: i64,
+    pub grid: [[TileState; GRID_SIZE as usize]; GRID_SIZE as usize],
+    /// Team of each player index
+    pub teams: [u8; MAX_PLAYERS as usize],
+    /// Symmetric matrix of relations between player indices
+    pub diplomacy: [[DiplomacyState; MAX_PLAYERS as usize]; MAX_PLAYERS as usize],
+    /// Attacks between the pair are refused until this timestamp after a treaty is broken
+    pub truce_until: [[i64; MAX_PLAYERS as usize]; MAX_PLAYERS as usize],
+    pub victory_condition: VictoryCondition,
+    /// Team currently meeting the Domination / King of the Hill hold requirement
+    pub control_team: Option<u8>,
+    /// Turn at which `control_team` took control
+    pub control_since: u64,
+    pub game_mode: GameMode,
+    /// Number of outer rings collapsed by the storm
+    pub storm_ring: u8,
+    /// Capture the flag: one flag per player index
+    pub flags: [Flag; MAX_PLAYERS as usize],
+    /// Capture the flag: captures scored per player index
+    pub captures: [u8; MAX_PLAYERS as usize],
+    pub barbarian_waves: u16,
+    /// Economy this game is played with
+    pub balance: Balance,
+    pub players: [Player; MAX_PLAYERS as usize],
+}
+
+/// Economy knobs a game is created with; the default is the stock ruleset
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Balance {
+    pub initial_gold: u64,
+    pub initial_wood: u64,
+    pub unit_cost_gold: u64,
+    pub defense_cost_wood: u64,
+    pub resource_per_tick: u64,
+    pub max_units: u8,
+}
+
+impl Default for Balance {
+    fn default() -> Self {
+        Balance {
+            initial_gold: INITIAL_GOLD,
+            initial_wood: INITIAL_WOOD,
+            unit_cost_gold: UNIT_COST_GOLD,
+            defense_cost_wood: DEFENSE_COST_WOOD,
+            resource_per_tick: RESOURCE_PER_TICK,
+            max_units: MAX_UNITS,
+        }
+    }
+}
+
+impl Balance {
+    /// Units must cost something and the cap must fit the starting units
+    pub fn is_valid(&self) -> bool {
+        self.unit_cost_gold > 0 && self.max_units >= INITIAL_UNITS
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Flag {
+    pub home_x: u8,
+    pub home_y: u8,
+    pub x: u8,
+    pub y: u8,
+    /// Player index whose stack on (x, y) is carrying the flag
+    pub carrier: Option<u8>,
+}
+
+impl Flag {
+    pub fn position(&self) -> (u8, u8) {
+        (self.x, self.y)
+    }
+
+    pub fn return_home(&mut self) {
+        self.x = self.home_x;
+        self.y = self.home_y;
+        self.carrier = None;
+    }
+}
+
+/// Relative weights per kind of action plus how safe an attack must be
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct StrategyProfile {
+    pub expansion: u8,
+    pub aggression: u8,
+    pub defense: u8,
+    pub saving: u8,
+    /// Minimum attacker strength as a percentage of the defender's (100 = any winning attack)
+    pub risk_threshold: u16,
+}
+
+impl StrategyProfile {
+    pub fn is_valid(&self) -> bool {
+        self.total_weight() > 0 && (MIN_RISK_THRESHOLD..=MAX_RISK_THRESHOLD).contains(&self.risk_threshold)
+    }
+
+    pub fn total_weight(&self) -> u16 {
+        self.expansion as u16 + self.aggression as u16 + self.defense as u16 + self.saving as u16
+    }
+
+    /// Whether `attackers` may take on `defenders` under this profile's risk threshold
+    pub fn accepts_attack(&self, attackers: u16, defenders: u16) -> bool {
+        attackers > defenders && attackers as u32 * 100 > defenders as u32 * self.risk_threshold as u32
+    }
+}
+
+// ==================== ACTIONS ====================
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Action {
+    Move { player: u8, from: (u8, u8), to: (u8, u8), count: u8 },
+    BuildDefense { player: u8, x: u8, y: u8 },
+    TrainUnits { player: u8, x: u8, y: u8, count: u8 },
+    CollectResources { player: u8 },
+    /// Battle royale: collapse the outermost standing ring
+    AdvanceStorm,
+    /// Hit the weakest tile of the player holding the most territory
+    BarbarianWave,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Outcome {
+    Moved { outcome: MoveOutcome, flag_captured: bool },
+    DefenseBuilt,
+    UnitsTrained { gold_spent: u64 },
+    Collected { gold: u64, wood: u64 },
+    StormAdvanced { ring: u8, units_destroyed: u64, eliminated: [bool; MAX_PLAYERS as usize] },
+    BarbariansAttacked { target: u8, x: u8, y: u8, strength: u8, defense: u16, overrun: bool },
+}
+
+/// What happened at the destination of a move
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MoveOutcome {
+    Claimed,
+    Reinforced,
+    Won { defender: u8, attackers_left: u8 },
+    Repelled { defenders_left: u8 },
+    CampCaptured { attackers_left: u8, reward: CampReward },
+    ResourceClaimed { resource_type: ResourceType, amount: u64 },
+}
+
+impl MoveOutcome {
+    pub fn captures_tile(&self) -> bool {
+        !matches!(self, MoveOutcome::Reinforced | MoveOutcome::Repelled { .. })
+    }
+}
+
+// ==================== RULES ====================
+
+impl GameState {
+    pub fn new(victory_condition: VictoryCondition, game_mode: GameMode) -> Self {
+        GameState {
+            status: GameStatus::Lobby,
+            player_count: 0,
+            turn: 0,
+            now: 0,
+            grid: [[TileState::Empty; GRID_SIZE as usize]; GRID_SIZE as usize],
+            teams: [0; MAX_PLAYERS as usize],
+            // Everyone starts at war
+            diplomacy: [[DiplomacyState::War; MAX_PLAYERS as usize]; MAX_PLAYERS as usize],
+            truce_until: [[0; MAX_PLAYERS as usize]; MAX_PLAYERS as usize],
+            victory_condition,
+            control_team: None,
+            control_since: 0,
+            game_mode,
+            storm_ring: 0,
+            flags: [Flag::default(); MAX_PLAYERS as usize],
+            captures: [0; MAX_PLAYERS as usize],
+            barbarian_waves: 0,
+            balance: Balance::default(),
+            players: [Player::default(); MAX_PLAYERS as usize],
+        }
+    }
+
+    /// Seat the next player on `team` with a 2x2 start whose top-left tile is `corner`.
+    /// Returns the new player index.
+    pub fn join(&mut self, team: u8, corner: (u8, u8)) -> Result<u8, EngineError> {
+        if self.status != GameStatus::Lobby {
+            return Err(EngineError::GameNotInLobby);
+        }
+        if self.player_count >= MAX_PLAYERS {
+            return Err(EngineError::GameFull);
+        }
+
+        let pi = self.player_count;
+        self.player_count += 1;
+        self.teams[pi as usize] = team;
+
+        // Teammates are permanently allied
+        for other in 0..pi {
+            if self.teams[other as usize] == team {
+                self.diplomacy[pi as usize][other as usize] = DiplomacyState::Alliance;
+                self.diplomacy[other as usize][pi as usize] = DiplomacyState::Alliance;
+            }
+        }
+
+        let (start_x, start_y) = corner;
+        for dx in 0..2 {
+            for dy in 0..2 {
+                self.grid[(start_y + dy) as usize][(start_x + dx) as usize] = TileState::Owned {
+                    player: pi,
+                    units: 1,
+                    has_defense: false,
+                    has_mine: false,
+                };
+            }
+        }
+
+        self.players[pi as usize] = Player {
+            team,
+            gold: self.balance.initial_gold,
+            wood: self.balance.initial_wood,
+            units: INITIAL_UNITS,
+            score: 4 * CLAIM_SCORE,
+            is_alive: true,
+        };
+
+        // Flag sits on the outer tile of the starting corner
+        if let GameMode::CaptureTheFlag { .. } = self.game_mode {
+            let home_x = if start_x == 0 { 0 } else { GRID_SIZE - 1 };
+            let home_y = if start_y == 0 { 0 } else { GRID_SIZE - 1 };
+            self.flags[pi as usize] = Flag {
+                home_x,
+                home_y,
+                x: home_x,
+                y: home_y,
+                carrier: None,
+            };
+        }
+        Ok(pi)
+    }
+
+    /// Place the center mines and neutral camps and open play
+    pub fn start(&mut self) -> Result<(), EngineError> {
+        if self.status != GameStatus::Lobby {
+            return Err(EngineError::GameNotInLobby);
+        }
+        if self.player_count < 2 {
+            return Err(EngineError::NotEnoughPlayers);
+        }
+
+        self.grid[3][3] = TileState::Resource { resource_type: ResourceType::Gold, amount: 500 };
+        self.grid[4][4] = TileState::Resource { resource_type: ResourceType::Gold, amount: 500 };
+        self.grid[3][4] = TileState::Resource { resource_type: ResourceType::Wood, amount: 300 };
+        self.grid[4][3] = TileState::Resource { resource_type: ResourceType::Wood, amount: 300 };
+
+        for (x, y, reward) in NEUTRAL_CAMPS {
+            self.grid[y][x] = TileState::Neutral { units: CAMP_UNITS, reward };
+        }
+
+        self.status = GameStatus::Active;
+        Ok(())
+    }
+
+    pub fn apply(&mut self, action: Action) -> Result<Outcome, EngineError> {
+        if self.status != GameStatus::Active {
+            return Err(EngineError::GameNotActive);
+        }
+        match action {
+            Action::Move { player, from, to, count } => self.apply_move(player, from, to, count),
+            Action::BuildDefense { player, x, y } => self.apply_build_defense(player, x, y),
+            Action::TrainUnits { player, x, y, count } => self.apply_train_units(player, x, y, count),
+            Action::CollectResources { player } => self.apply_collect_resources(player),
+            Action::AdvanceStorm => self.apply_advance_storm(),
+            Action::BarbarianWave => self.apply_barbarian_wave(),
+        }
+    }
+
+    fn acting_player(&mut self, pi: u8) -> Result<&mut Player, EngineError> {
+        if pi >= self.player_count {
+            return Err(EngineError::InvalidPlayer);
+        }
+        let player = &mut self.players[pi as usize];
+        if !player.is_alive {
+            return Err(EngineError::PlayerEliminated);
+        }
+        Ok(player)
+    }
+
+    fn apply_move(&mut self, pi: u8, from: (u8, u8), to: (u8, u8), unit_count: u8) -> Result<Outcome, EngineError> {
+        self.acting_player(pi)?;
+        let ((from_x, from_y), (to_x, to_y)) = (from, to);
+        if from_x >= GRID_SIZE || from_y >= GRID_SIZE || to_x >= GRID_SIZE || to_y >= GRID_SIZE {
+            return Err(EngineError::OutOfBounds);
+        }
+
+        // Must be adjacent, or two tiles in a straight line across a tile an ally holds
+        let dx = (from_x as i16 - to_x as i16).unsigned_abs() as u8;
+        let dy = (from_y as i16 - to_y as i16).unsigned_abs() as u8;
+        let adjacent = dx <= 1 && dy <= 1 && dx + dy > 0;
+        let crosses_ally = !adjacent
+            && matches!(dx, 0 | 2)
+            && matches!(dy, 0 | 2)
+            && match self.tile((from_x + to_x) / 2, (from_y + to_y) / 2) {
+                TileState::Owned { player, .. } => self.is_ally(pi, player),
+                _ => false,
+            };
+        if !adjacent && !crosses_ally {
+            return Err(EngineError::NotAdjacent);
+        }
+
+        let (fx, fy, tx, ty) = (from_x as usize, from_y as usize, to_x as usize, to_y as usize);
+
+        // Verify ownership of source
+        match self.grid[fy][fx] {
+            TileState::Owned { player, units, .. } => {
+                if player != pi {
+                    return Err(EngineError::NotYourTile);
+                }
+                if units < unit_count || unit_count == 0 {
+                    return Err(EngineError::NotEnoughUnits);
+                }
+            }
+            _ => return Err(EngineError::NotYourTile),
+        }
+
+        // Check the destination before touching the board
+        let dest = self.grid[ty][tx];
+        match dest {
+            TileState::Collapsed => return Err(EngineError::TileCollapsed),
+            TileState::Owned { player: owner, units, .. } if self.is_ally(pi, owner) => {
+                // Reinforcing an ally hands the units over to them
+                if units.checked_add(unit_count).is_none() {
+                    return Err(EngineError::StackFull);
+                }
+                if self.players[owner as usize].units as u16 + unit_count as u16 > self.balance.max_units as u16 {
+                    return Err(EngineError::MaxUnitsReached);
+                }
+            }
+            TileState::Owned { player: owner, .. } if owner != pi => {
+                if self.diplomacy_between(pi, owner) == DiplomacyState::Peace {
+                    return Err(EngineError::AtPeace);
+                }
+                if self.now < self.truce_until[pi as usize][owner as usize] {
+                    return Err(EngineError::TreatyCooldown);
+                }
+            }
+            TileState::Owned { units, .. } if units.checked_add(unit_count).is_none() => {
+                return Err(EngineError::StackFull);
+            }
+            _ => {}
+        }
+
+        // Remove units from source
+        if let TileState::Owned { ref mut units, .. } = self.grid[fy][fx] {
+            *units -= unit_count;
+        }
+
+        let ally = match dest {
+            TileState::Owned { player: owner, .. } if self.is_ally(pi, owner) => Some(owner),
+            _ => None,
+        };
+        if let Some(ally) = ally {
+            self.players[pi as usize].units = self.players[pi as usize].units.saturating_sub(unit_count);
+            self.players[ally as usize].units += unit_count;
+        }
+
+        let player = &mut self.players[pi as usize];
+        let outcome = match dest {
+            TileState::Empty => {
+                self.grid[ty][tx] = TileState::Owned {
+                    player: pi,
+                    units: unit_count,
+                    has_defense: false,
+                    has_mine: false,
+                };
+                player.score += CLAIM_SCORE;
+                MoveOutcome::Claimed
+            }
+            TileState::Owned { player: owner, units: def_units, has_defense, has_mine } => {
+                if owner == pi || ally.is_some() {
+                    // Reinforce our own or an ally's stack
+                    if let TileState::Owned { ref mut units, .. } = self.grid[ty][tx] {
+                        *units += unit_count;
+                    }
+                    MoveOutcome::Reinforced
+                } else {
+                    // Combat
+                    let atk = unit_count as u16;
+                    let def = def_units as u16 + if has_defense { DEFENSE_BONUS } else { 0 };
+
+                    if atk > def {
+                        let remaining = ((atk - def) as u8).max(1);
+                        self.grid[ty][tx] = TileState::Owned {
+                            player: pi,
+                            units: remaining,
+                            has_defense: false,
+                            has_mine,
+                        };
+                        player.score += CAPTURE_SCORE;
+                        MoveOutcome::Won { defender: owner, attackers_left: remaining }
+                    } else {
+                        let remaining = ((def - atk) as u8).max(1);
+                        self.grid[ty][tx] = TileState::Owned {
+                            player: owner,
+                            units: remaining,
+                            has_defense,
+                            has_mine,
+                        };
+                        MoveOutcome::Repelled { defenders_left: remaining }
+                    }
+                }
+            }
+            TileState::Collapsed => unreachable!("collapsed destinations are rejected above"),
+            TileState::Neutral { units: def_units, reward } => {
+                let atk = unit_count as u16;
+                let def = def_units as u16;
+
+                if atk > def {
+                    let mut remaining = ((atk - def) as u8).max(1);
+                    match reward {
+                        CampReward::Gold { amount } => player.gold += amount,
+                        CampReward::Wood { amount } => player.wood += amount,
+                        CampReward::Units { count } => {
+                            remaining = remaining.saturating_add(count);
+                            player.units = player.units.saturating_add(count);
+                        }
+                    }
+                    self.grid[ty][tx] = TileState::Owned {
+                        player: pi,
+                        units: remaining,
+                        has_defense: false,
+                        has_mine: false,
+                    };
+                    player.score += CAMP_CAPTURE_SCORE;
+                    MoveOutcome::CampCaptured { attackers_left: remaining, reward }
+                } else {
+                    let remaining = ((def - atk) as u8).max(1);
+                    self.grid[ty][tx] = TileState::Neutral { units: remaining, reward };
+                    MoveOutcome::Repelled { defenders_left: remaining }
+                }
+            }
+            TileState::Resource { resource_type, amount } => {
+                match resource_type {
+                    ResourceType::Gold => player.gold += amount,
+                    ResourceType::Wood => player.wood += amount,
+                }
+                self.grid[ty][tx] = TileState::Owned {
+                    player: pi,
+                    units: unit_count,
+                    has_defense: false,
+                    has_mine: true,
+                };
+                player.score += RESOURCE_SCORE;
+                MoveOutcome::ResourceClaimed { resource_type, amount }
+            }
+        };
+
+        let mut flag_captured = false;
+        if let GameMode::CaptureTheFlag { .. } = self.game_mode {
+            if self.resolve_flags(pi, from, to) {
+                self.captures[pi as usize] += 1;
+                self.players[pi as usize].score += CTF_CAPTURE_SCORE;
+                flag_captured = true;
+            }
+        }
+
+        self.turn += 1;
+        self.refresh_control();
+        Ok(Outcome::Moved { outcome, flag_captured })
+    }
+
+    fn apply_build_defense(&mut self, pi: u8, x: u8, y: u8) -> Result<Outcome, EngineError> {
+        let balance = self.balance;
+        let player = self.acting_player(pi)?;
+        if x >= GRID_SIZE || y >= GRID_SIZE {
+            return Err(EngineError::OutOfBounds);
+        }
+        if player.wood < balance.defense_cost_wood {
+            return Err(EngineError::NotEnoughResources);
+        }
+
+        match self.grid[y as usize][x as usize] {
+            TileState::Owned { player, has_defense, .. } => {
+                if player != pi {
+                    return Err(EngineError::NotYourTile);
+                }
+                if has_defense {
+                    return Err(EngineError::AlreadyHasDefense);
+                }
+            }
+            _ => return Err(EngineError::NotYourTile),
+        }
+
+        if let TileState::Owned { ref mut has_defense, .. } = self.grid[y as usize][x as usize] {
+            *has_defense = true;
+        }
+        let player = &mut self.players[pi as usize];
+        player.wood -= balance.defense_cost_wood;
+        player.score += DEFENSE_SCORE;
+        Ok(Outcome::DefenseBuilt)
+    }
+
+    fn apply_train_units(&mut self, pi: u8, x: u8, y: u8, count: u8) -> Result<Outcome, EngineError> {
+        let balance = self.balance;
+        let player = self.acting_player(pi)?;
+        if x >= GRID_SIZE || y >= GRID_SIZE {
+            return Err(EngineError::OutOfBounds);
+        }
+
+        let cost = count as u64 * balance.unit_cost_gold;
+        if player.gold < cost {
+            return Err(EngineError::NotEnoughResources);
+        }
+        if player.units as u16 + count as u16 > balance.max_units as u16 {
+            return Err(EngineError::MaxUnitsReached);
+        }
+
+        match self.grid[y as usize][x as usize] {
+            TileState::Owned { player, units, .. } if player == pi => {
+                if units.checked_add(count).is_none() {
+                    return Err(EngineError::StackFull);
+                }
+            }
+            _ => return Err(EngineError::NotYourTile),
+        }
+
+        if let TileState::Owned { ref mut units, .. } = self.grid[y as usize][x as usize] {
+            *units += count;
+        }
+        let player = &mut self.players[pi as usize];
+        player.gold -= cost;
+        player.units += count;
+        Ok(Outcome::UnitsTrained { gold_spent: cost })
+    }
+
+    fn apply_collect_resources(&mut self, pi: u8) -> Result<Outcome, EngineError> {
+        self.acting_player(pi)?;
+
+        let mut gold: u64 = 0;
+        let mut wood: u64 = 0;
+        for tile in self.grid.iter().flatten() {
+            if let TileState::Owned { player, has_mine, .. } = *tile {
+                if player == pi {
+                    gold += self.balance.resource_per_tick;
+                    wood += self.balance.resource_per_tick;
+                    if has_mine {
+                        gold += self.balance.resource_per_tick * 2;
+                    }
+                }
+            }
+        }
+
+        let player = &mut self.players[pi as usize];
+        player.gold += gold;
+        player.wood += wood;
+        Ok(Outcome::Collected { gold, wood })
+    }
+
+    fn apply_advance_storm(&mut self) -> Result<Outcome, EngineError> {
+        let GameMode::BattleRoyale { .. } = self.game_mode else {
+            return Err(EngineError::WrongGameMode);
+        };
+        if self.storm_ring >= MAX_STORM_RINGS {
+            return Err(EngineError::StormComplete);
+        }
+
+        // Collapse the ring, destroying any units standing on it
+        let ring = self.storm_ring as usize;
+        let last = GRID_SIZE as usize - 1;
+        let mut units_destroyed: u64 = 0;
+        for y in 0..GRID_SIZE as usize {
+            for x in 0..GRID_SIZE as usize {
+                if x.min(y).min(last - x).min(last - y) != ring {
+                    continue;
+                }
+                if let TileState::Owned { player, units, .. } = self.grid[y][x] {
+                    units_destroyed += units as u64;
+                    let owner = &mut self.players[player as usize];
+                    owner.units = owner.units.saturating_sub(units);
+                }
+                self.grid[y][x] = TileState::Collapsed;
+            }
+        }
+
+        self.storm_ring += 1;
+        self.refresh_control();
+        let eliminated = self.eliminate_landless();
+        Ok(Outcome::StormAdvanced { ring: ring as u8, units_destroyed, eliminated })
+    }
+
+    fn apply_barbarian_wave(&mut self) -> Result<Outcome, EngineError> {
+        let leader = (0..self.player_count)
+            .max_by_key(|&i| (self.tiles_owned(i), Reverse(i)))
+            .ok_or(EngineError::NotEnoughPlayers)?;
+
+        // Hit the leader's weakest tile
+        let mut target: Option<(u8, u8, u16)> = None;
+        for (x, y, units, has_defense) in self.owned_tiles(leader) {
+            let def = units as u16 + if has_defense { DEFENSE_BONUS } else { 0 };
+            if target.is_none_or(|(_, _, best)| def < best) {
+                target = Some((x, y, def));
+            }
+        }
+        let (x, y, def) = target.ok_or(EngineError::NotEnoughPlayers)?;
+
+        let atk = (BARBARIAN_BASE_STRENGTH as u16 + self.barbarian_waves).min(u8::MAX as u16);
+        let overrun = atk > def;
+        let tile = &mut self.grid[y as usize][x as usize];
+        if overrun {
+            *tile = TileState::Neutral {
+                units: (atk - def) as u8,
+                reward: CampReward::Gold { amount: BARBARIAN_REWARD_GOLD },
+            };
+        } else if let TileState::Owned { ref mut units, ref mut has_defense, .. } = tile {
+            // Defense absorbs the first hits, then the garrison
+            let mut damage = atk;
+            if *has_defense {
+                *has_defense = damage < DEFENSE_BONUS;
+                damage = damage.saturating_sub(DEFENSE_BONUS);
+            }
+            *units = units.saturating_sub(damage as u8).max(1);
+        }
+
+        self.barbarian_waves += 1;
+        self.refresh_control();
+        Ok(Outcome::BarbariansAttacked { target: leader, x, y, strength: atk as u8, defense: def, overrun })
+    }
+
+    /// Mark players left without any tiles as eliminated; returns who was newly eliminated
+    pub fn eliminate_landless(&mut self) -> [bool; MAX_PLAYERS as usize] {
+        let mut eliminated = [false; MAX_PLAYERS as usize];
+        for pi in 0..self.player_count {
+            if self.players[pi as usize].is_alive && self.tiles_owned(pi) == 0 {
+                self.players[pi as usize].is_alive = false;
+                eliminated[pi as usize] = true;
+            }
+        }
+        eliminated
+    }
+
+    /// Move, drop, pick up and return flags after `pi` moved a stack from `from` to `to`.
+    /// Returns true if the move scored a capture.
+    fn resolve_flags(&mut self, pi: u8, from: (u8, u8), to: (u8, u8)) -> bool {
+        let dest_owner = match self.tile(to.0, to.1) {
+            TileState::Owned { player, .. } => Some(player),
+            _ => None,
+        };
+        let holds_dest = dest_owner == Some(pi);
+        // Units `pi` kept back on the source tile still guard anything carried there
+        let holds_source = matches!(
+            self.tile(from.0, from.1),
+            TileState::Owned { player, units, .. } if player == pi && units > 0
+        );
+        let base = (self.flags[pi as usize].home_x, self.flags[pi as usize].home_y);
+        let mut captured = false;
+
+        for f in 0..self.player_count as usize {
+            let own_team = self.teams[f] == self.teams[pi as usize];
+            let flag = &mut self.flags[f];
+
+            if flag.carrier == Some(pi) && flag.position() == from {
+                // The flag travels with the moving stack, or drops where it stood once the
+                // carrying stack is gone
+                if holds_dest {
+                    (flag.x, flag.y) = to;
+                } else if !holds_source {
+                    flag.carrier = None;
+                }
+            } else if holds_dest && flag.position() == to && flag.carrier.is_some() {
+                // Carrying stack lost the tile
+                flag.carrier = None;
+            }
+
+            if holds_dest && flag.position() == to && flag.carrier.is_none() {
+                if !own_team {
+                    flag.carrier = Some(pi);
+                } else {
+                    flag.return_home();
+                }
+            }
+
+            if flag.carrier == Some(pi) && flag.position() == to && to == base {
+                flag.return_home();
+                captured = true;
+            }
+        }
+        captured
+    }
+
+    /// Restart the hold counter whenever the controlling team changes
+    pub fn refresh_control(&mut self) {
+        let controller = self.controlling_team();
+        if controller != self.control_team {
+            self.control_team = controller;
+            self.control_since = self.turn;
+        }
+    }
+}
+
+// ==================== QUERIES ====================
+
+impl GameState {
+    pub fn tile(&self, x: u8, y: u8) -> TileState {
+        self.grid[y as usize][x as usize]
+    }
+
+    /// Coordinates a stack on (x, y) can move to, diagonals included
+    pub fn neighbors(x: u8, y: u8) -> impl Iterator<Item = (u8, u8)> {
+        (-1i8..=1)
+            .flat_map(|dy| (-1i8..=1).map(move |dx| (dx, dy)))
+            .filter(|&(dx, dy)| dx != 0 || dy != 0)
+            .filter_map(move |(dx, dy)| {
+                let (nx, ny) = (x as i8 + dx, y as i8 + dy);
+                let inside = (0..GRID_SIZE as i8).contains(&nx) && (0..GRID_SIZE as i8).contains(&ny);
+                inside.then_some((nx as u8, ny as u8))
+            })
+    }
+
+    /// (x, y, units, has_defense) of every tile `pi` owns, row by row
+    pub fn owned_tiles(&self, pi: u8) -> impl Iterator<Item = (u8, u8, u8, bool)> + Clone + '_ {
+        (0..GRID_SIZE)
+            .flat_map(|y| (0..GRID_SIZE).map(move |x| (x, y)))
+            .filter_map(move |(x, y)| match self.tile(x, y) {
+                TileState::Owned { player, units, has_defense, .. } if player == pi => {
+                    Some((x, y, units, has_defense))
+                }
+                _ => None,
+            })
+    }
+
+    pub fn diplomacy_between(&self, a: u8, b: u8) -> DiplomacyState {
+        self.diplomacy[a as usize][b as usize]
+    }
+
+    /// Whether `b` is another player allied with `a` (teammates always are)
+    pub fn is_ally(&self, a: u8, b: u8) -> bool {
+        a != b && self.diplomacy_between(a, b) == DiplomacyState::Alliance
+    }
+
+    pub fn are_teammates(&self, a: u8, b: u8) -> bool {
+        self.teams[a as usize] == self.teams[b as usize]
+    }
+
+    /// Whether `pi` may attack tiles held by `owner` right now
+    pub fn can_attack(&self, pi: u8, owner: u8) -> bool {
+        owner != pi
+            && !self.are_teammates(pi, owner)
+            && self.diplomacy_between(pi, owner) == DiplomacyState::War
+            && self.now >= self.truce_until[pi as usize][owner as usize]
+    }
+
+    pub fn tiles_owned(&self, player_index: u8) -> u32 {
+        self.owned_tiles(player_index).count() as u32
+    }
+
+    pub fn team_tiles(&self, team: u8) -> u32 {
+        (0..self.player_count)
+            .filter(|&i| self.teams[i as usize] == team)
+            .map(|i| self.tiles_owned(i))
+            .sum()
+    }
+
+    pub fn team_size(&self, team: u8) -> u64 {
+        (0..self.player_count).filter(|&i| self.teams[i as usize] == team).count() as u64
+    }
+
+    /// Team with strictly the most tiles, None on a tie
+    pub fn territory_leader(&self) -> Option<u8> {
+        let mut best: Option<(u8, u32)> = None;
+        let mut tied = false;
+        for i in 0..self.player_count {
+            let team = self.teams[i as usize];
+            if (0..i).any(|j| self.teams[j as usize] == team) {
+                continue;
+            }
+            let tiles = self.team_tiles(team);
+            match best {
+                Some((_, best_tiles)) if tiles < best_tiles => {}
+                Some((_, best_tiles)) if tiles == best_tiles => tied = true,
+                _ => {
+                    best = Some((team, tiles));
+                    tied = false;
+                }
+            }
+        }
+        if tied {
+            None
+        } else {
+            best.map(|(team, _)| team)
+        }
+    }
+
+    /// Team with strictly the highest score, None on a tie
+    pub fn score_leader(&self) -> Option<u8> {
+        let team_scores = self.team_scores();
+        let best = *team_scores.iter().max()?;
+        let mut leaders = (0..MAX_PLAYERS)
+            .filter(|&team| self.team_size(team) > 0 && team_scores[team as usize] == best);
+        match (leaders.next(), leaders.next()) {
+            (Some(team), None) => Some(team),
+            _ => None,
+        }
+    }
+
+    pub fn team_scores(&self) -> [u64; MAX_PLAYERS as usize] {
+        let mut team_scores = [0u64; MAX_PLAYERS as usize];
+        for player in &self.players[..self.player_count as usize] {
+            team_scores[player.team as usize] += player.score;
+        }
+        team_scores
+    }
+
+    /// Team currently holding the tiles the victory condition asks for
+    pub fn controlling_team(&self) -> Option<u8> {
+        match self.victory_condition {
+            VictoryCondition::Domination { percent, .. } => {
+                let needed = (GRID_SIZE as u32 * GRID_SIZE as u32 * percent as u32).div_ceil(100);
+                (0..MAX_PLAYERS)
+                    .filter(|&team| self.team_size(team) > 0)
+                    .find(|&team| self.team_tiles(team) >= needed)
+            }
+            VictoryCondition::KingOfTheHill { .. } => {
+                let mut owners = CENTER_TILES.iter().map(|&(x, y)| match self.grid[y][x] {
+                    TileState::Owned { player, .. } => Some(self.teams[player as usize]),
+                    _ => None,
+                });
+                let first = owners.next().flatten()?;
+                owners.all(|team| team == Some(first)).then_some(first)
+            }
+            _ => None,
+        }
+    }
+
+    /// Team that reached the capture target in capture the flag
+    pub fn ctf_winner(&self) -> Option<u8> {
+        let GameMode::CaptureTheFlag { captures_to_win } = self.game_mode else {
+            return None;
+        };
+        (0..MAX_PLAYERS).filter(|&team| self.team_size(team) > 0).find(|&team| {
+            let captures: u32 = (0..self.player_count)
+                .filter(|&i| self.teams[i as usize] == team)
+                .map(|i| self.captures[i as usize] as u32)
+                .sum();
+            captures >= captures_to_win as u32
+        })
+    }
+
+    /// Result of the victory condition: None while undecided, Some(None) for a draw.
+    /// Needs every player filled in.
+    pub fn victory(&self) -> Option<Option<u8>> {
+        // A capture the flag win ends the game regardless of the victory condition
+        if let Some(team) = self.ctf_winner() {
+            return Some(Some(team));
+        }
+        match self.victory_condition {
+            VictoryCondition::Conquest => {
+                let mut alive_teams = self.players[..self.player_count as usize]
+                    .iter()
+                    .filter(|player| player.is_alive)
+                    .map(|player| player.team);
+                match alive_teams.next() {
+                    None => Some(None),
+                    Some(team) if alive_teams.all(|t| t == team) => Some(Some(team)),
+                    Some(_) => None,
+                }
+            }
+            VictoryCondition::Domination { ticks, .. } | VictoryCondition::KingOfTheHill { ticks } => {
+                match self.control_team {
+                    Some(team) if self.turn - self.control_since >= ticks => Some(Some(team)),
+                    _ => None,
+                }
+            }
+            VictoryCondition::ScoreRace { target } => {
+                let reached = self.team_scores().iter().any(|&score| score >= target);
+                reached.then(|| self.score_leader())
+            }
+            VictoryCondition::Timed { deadline } => (self.now >= deadline).then(|| self.score_leader()),
+        }
+    }
+}
+
+// ==================== AUTOPLAY ====================
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AutoPlan {
+    Attack,
+    Fortify,
+    Expand,
+    Save,
+}
+
+impl GameState {
+    /// Deterministic built-in policy for `pi` playing `profile` on its `tick`-th autoplay turn.
+    ///
+    /// Ticks walk the plans in weighted round robin; a plan with nothing to do
+    /// falls back to the remaining plans by weight, then to collecting.
+    pub fn autoplay_action(&self, pi: u8, profile: &StrategyProfile, tick: u64) -> Action {
+        let mut plans = [
+            (profile.aggression, AutoPlan::Attack),
+            (profile.defense, AutoPlan::Fortify),
+            (profile.expansion, AutoPlan::Expand),
+            (profile.saving, AutoPlan::Save),
+        ];
+
+        let mut pick = (tick % profile.total_weight().max(1) as u64) as u16;
+        let chosen = plans
+            .iter()
+            .position(|&(weight, _)| {
+                if pick < weight as u16 {
+                    return true;
+                }
+                pick -= weight as u16;
+                false
+            })
+            .unwrap_or(0);
+        plans[..=chosen].rotate_right(1);
+        // Stable insertion sort of the fallbacks by weight (no alloc in no_std)
+        for i in 2..plans.len() {
+            let mut j = i;
+            while j > 1 && plans[j - 1].0 < plans[j].0 {
+                plans.swap(j - 1, j);
+                j -= 1;
+            }
+        }
+
+        for (weight, plan) in plans {
+            if weight == 0 {
+                continue;
+            }
+            let action = match plan {
+                AutoPlan::Attack => self.autoplay_attack(pi, profile),
+                AutoPlan::Fortify => self.autoplay_fortify(pi),
+                AutoPlan::Expand => self.autoplay_expand(pi),
+                AutoPlan::Save => Some(Action::CollectResources { player: pi }),
+            };
+            if let Some(action) = action {
+                return action;
+            }
+        }
+        Action::CollectResources { player: pi }
+    }
+
+    /// Attack: send a whole stack at the weakest adjacent enemy tile the profile is willing to hit
+    pub fn autoplay_attack(&self, pi: u8, profile: &StrategyProfile) -> Option<Action> {
+        let mut best: Option<(u16, Action)> = None;
+        for (x, y, units, _) in self.owned_tiles(pi) {
+            for (nx, ny) in Self::neighbors(x, y) {
+                if let TileState::Owned { player, units: def_units, has_defense, .. } = self.tile(nx, ny) {
+                    let def = def_units as u16 + if has_defense { DEFENSE_BONUS } else { 0 };
+                    if self.can_attack(pi, player)
+                        && profile.accepts_attack(units as u16, def)
+                        && best.is_none_or(|(best_def, _)| def < best_def)
+                    {
+                        best = Some((def, Action::Move { player: pi, from: (x, y), to: (nx, ny), count: units }));
+                    }
+                }
+            }
+        }
+        best.map(|(_, action)| action)
+    }
+
+    /// Fortify: defend the weakest frontier tile, otherwise train troops on it
+    pub fn autoplay_fortify(&self, pi: u8) -> Option<Action> {
+        let player = &self.players[pi as usize];
+        let frontier = self.owned_tiles(pi).filter(|&(x, y, _, _)| {
+            Self::neighbors(x, y).any(|(nx, ny)| match self.tile(nx, ny) {
+                TileState::Owned { player, .. } => {
+                    player != pi && self.diplomacy_between(pi, player) != DiplomacyState::Alliance
+                }
+                TileState::Collapsed => false,
+                _ => true,
+            })
+        });
+        let weakest = |&(x, y, units, _): &(u8, u8, u8, bool)| (units, y, x);
+
+        if player.wood >= self.balance.defense_cost_wood {
+            let undefended = frontier.clone().filter(|&(_, _, _, has_defense)| !has_defense);
+            if let Some((x, y, _, _)) = undefended.min_by_key(weakest) {
+                return Some(Action::BuildDefense { player: pi, x, y });
+            }
+        }
+        let affordable = (player.gold / self.balance.unit_cost_gold)
+            .min(self.balance.max_units.saturating_sub(player.units) as u64) as u8;
+        if let Some((x, y, units, _)) = frontier.min_by_key(weakest) {
+            let count = affordable.min(u8::MAX - units);
+            if count > 0 {
+                return Some(Action::TrainUnits { player: pi, x, y, count });
+            }
+        }
+        None
+    }
+
+    /// Expand: grab adjacent resources, then beatable camps, then empty land toward the center
+    pub fn autoplay_expand(&self, pi: u8) -> Option<Action> {
+        let mut best: Option<((u8, u8), Action)> = None;
+        for (x, y, units, _) in self.owned_tiles(pi) {
+            if units == 0 {
+                continue;
+            }
+            for (nx, ny) in Self::neighbors(x, y) {
+                let (priority, count) = match self.tile(nx, ny) {
+                    TileState::Resource { .. } => (3, units),
+                    TileState::Neutral { units: camp_units, .. } if units > camp_units => (2, units),
+                    TileState::Empty => (1, units.div_ceil(2)),
+                    _ => continue,
+                };
+                // Prefer higher priority, then tiles closer to the center
+                let center_distance =
+                    (2 * nx as i16 - 7).unsigned_abs() as u8 + (2 * ny as i16 - 7).unsigned_abs() as u8;
+                let rank = (priority, u8::MAX - center_distance);
+                if best.is_none_or(|(best_rank, _)| rank > best_rank) {
+                    best = Some((rank, Action::Move { player: pi, from: (x, y), to: (nx, ny), count }));
+                }
+            }
+        }
+        best.map(|(_, action)| action)
+    }
+}
+
+// ==================== ENUMS ====================
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GameStatus {
+    Lobby,
+    Active,
+    Finished,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TileState {
+    Empty,
+    Owned {
+        player: u8,
+        units: u8,
+        has_defense: bool,
+        has_mine: bool,
+    },
+    Resource {
+        resource_type: ResourceType,
+        amount: u64,
+    },
+    /// Swallowed by the battle royale storm, impassable
+    Collapsed,
+    /// Neutral camp guarded by `units`, pays `reward` to whoever takes it
+    Neutral {
+        units: u8,
+        reward: CampReward,
+    },
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CampReward {
+    Gold { amount: u64 },
+    Wood { amount: u64 },
+    Units { count: u8 },
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ResourceType {
+    Gold,
+    Wood,
+}
+
+impl ResourceType {
+    pub fn other(self) -> Self {
+        match self {
+            ResourceType::Gold => ResourceType::Wood,
+            ResourceType::Wood => ResourceType::Gold,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DiplomacyState {
+    War,
+    Peace,
+    Alliance,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GameMode {
+    Standard,
+    /// The map shrinks by one ring every `shrink_interval_slots` slots
+    BattleRoyale { shrink_interval_slots: u64 },
+    /// Carry an enemy flag back to your own flag's tile; first team to `captures_to_win` wins
+    CaptureTheFlag { captures_to_win: u8 },
+}
+
+impl GameMode {
+    pub fn is_valid(&self) -> bool {
+        match *self {
+            GameMode::Standard => true,
+            GameMode::BattleRoyale { shrink_interval_slots } => shrink_interval_slots > 0,
+            GameMode::CaptureTheFlag { captures_to_win } => captures_to_win > 0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum VictoryCondition {
+    /// Last team with territory wins
+    Conquest,
+    /// Hold `percent` of all tiles for `ticks` turns
+    Domination { percent: u8, ticks: u64 },
+    /// First team to `target` points
+    ScoreRace { target: u64 },
+    /// Highest score once `deadline` (unix timestamp) passes
+    Timed { deadline: i64 },
+    /// Hold every center resource tile for `ticks` consecutive turns
+    KingOfTheHill { ticks: u64 },
+}
+
+impl VictoryCondition {
+    pub fn is_valid(&self) -> bool {
+        match *self {
+            VictoryCondition::Conquest => true,
+            VictoryCondition::Domination { percent, ticks } => percent > 0 && percent <= 100 && ticks > 0,
+            VictoryCondition::ScoreRace { target } => target > 0,
+            VictoryCondition::Timed { deadline } => deadline > 0,
+            VictoryCondition::KingOfTheHill { ticks } => ticks > 0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StrategyMode {
+    Aggressive,
+    Defensive,
+    Balanced,
+    Economic,
+}
+
+impl StrategyMode {
+    /// Preset profile used when the player has not set a custom one
+    pub fn profile(&self) -> StrategyProfile {
+        let (expansion, aggression, defense, saving, risk_threshold) = match self {
+            StrategyMode::Aggressive => (1, 3, 0, 0, 100),
+            StrategyMode::Defensive => (0, 0, 1, 0, 200),
+            StrategyMode::Balanced => (1, 1, 1, 0, 150),
+            StrategyMode::Economic => (1, 0, 0, 1, 150),
+        };
+        StrategyProfile { expansion, aggression, defense, saving, risk_threshold }
+    }
+}
+
+// ==================== ERRORS ====================
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EngineError {
+    GameNotInLobby,
+    GameFull,
+    GameNotActive,
+    NotEnoughPlayers,
+    InvalidPlayer,
+    PlayerEliminated,
+    OutOfBounds,
+    NotAdjacent,
+    NotYourTile,
+    NotEnoughUnits,
+    NotEnoughResources,
+    AlreadyHasDefense,
+    MaxUnitsReached,
+    AtPeace,
+    TreatyCooldown,
+    TileCollapsed,
+    WrongGameMode,
+    StormComplete,
+    StackFull,
+}
+
+impl fmt::Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            EngineError::GameNotInLobby => "Game is not in lobby state",
+            EngineError::GameFull => "Game is full",
+            EngineError::GameNotActive => "Game is not active",
+            EngineError::NotEnoughPlayers => "Not enough players",
+            EngineError::InvalidPlayer => "No such player in this game",
+            EngineError::PlayerEliminated => "Player has been eliminated",
+            EngineError::OutOfBounds => "Coordinates out of bounds",
+            EngineError::NotAdjacent => "Tiles are not adjacent",
+            EngineError::NotYourTile => "Not your tile",
+            EngineError::NotEnoughUnits => "Not enough units",
+            EngineError::NotEnoughResources => "Not enough resources",
+            EngineError::AlreadyHasDefense => "Tile already has defense",
+            EngineError::MaxUnitsReached => "Max units reached",
+            EngineError::AtPeace => "Cannot attack a player you are at peace with",
+            EngineError::TreatyCooldown => "Treaty was broken too recently to attack",
+            EngineError::TileCollapsed => "Tile has collapsed",
+            EngineError::WrongGameMode => "Not available in this game mode",
+            EngineError::StormComplete => "The storm has fully closed in",
+            EngineError::StackFull => "A tile cannot hold more than 255 units",
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for EngineError {}
+
+// ==================== TESTS ====================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two-player free-for-all: P0 starts top-left, P1 bottom-right
+    fn duel(victory_condition: VictoryCondition, game_mode: GameMode) -> GameState {
+        let mut state = GameState::new(victory_condition, game_mode);
+        state.join(0, (0, 0)).unwrap();
+        state.join(1, (6, 6)).unwrap();
+        state.start().unwrap();
+        state
+    }
+
+    fn standard() -> GameState {
+        duel(VictoryCondition::Conquest, GameMode::Standard)
+    }
+
+    fn owned(player: u8, units: u8) -> TileState {
+        TileState::Owned { player, units, has_defense: false, has_mine: false }
+    }
+
+    fn set(state: &mut GameState, x: u8, y: u8, tile: TileState) {
+        state.grid[y as usize][x as usize] = tile;
+    }
+
+    fn mv(player: u8, from: (u8, u8), to: (u8, u8), count: u8) -> Action {
+        Action::Move { player, from, to, count }
+    }
+
+    fn moved(outcome: MoveOutcome) -> Result<Outcome, EngineError> {
+        Ok(Outcome::Moved { outcome, flag_captured: false })
+    }
+
+    #[test]
+    fn lobby_seats_players_and_start_lays_out_the_board() {
+        let mut state = GameState::new(VictoryCondition::Conquest, GameMode::Standard);
+        assert_eq!(state.apply(Action::CollectResources { player: 0 }), Err(EngineError::GameNotActive));
+        assert_eq!(state.join(0, (0, 0)), Ok(0));
+        assert_eq!(state.start(), Err(EngineError::NotEnoughPlayers));
+        assert_eq!(state.join(1, (6, 0)), Ok(1));
+        assert_eq!(state.join(0, (0, 6)), Ok(2));
+        assert_eq!(state.join(1, (6, 6)), Ok(3));
+        assert_eq!(state.join(0, (2, 2)), Err(EngineError::GameFull));
+
+        // Teammates start allied, opponents at war
+        assert_eq!(state.diplomacy_between(0, 2), DiplomacyState::Alliance);
+        assert_eq!(state.diplomacy_between(0, 1), DiplomacyState::War);
+        assert_eq!(state.tiles_owned(3), 4);
+        assert_eq!(state.players[1].units, INITIAL_UNITS);
+
+        state.start().unwrap();
+        assert_eq!(state.status, GameStatus::Active);
+        assert_eq!(state.tile(3, 3), TileState::Resource { resource_type: ResourceType::Gold, amount: 500 });
+        assert!(matches!(state.tile(3, 1), TileState::Neutral { units: CAMP_UNITS, .. }));
+        assert_eq!(state.join(0, (2, 2)), Err(EngineError::GameNotInLobby));
+    }
+
+    #[test]
+    fn move_claims_empty_tiles_and_reinforces_own() {
+        let mut state = standard();
+        assert_eq!(state.apply(mv(0, (1, 1), (2, 2), 1)), moved(MoveOutcome::Claimed));
+        assert_eq!(state.tile(2, 2), owned(0, 1));
+        assert_eq!(state.tile(1, 1), owned(0, 0));
+        assert_eq!(state.players[0].score, 5 * CLAIM_SCORE);
+        assert_eq!(state.turn, 1);
+
+        assert_eq!(state.apply(mv(0, (1, 0), (1, 1), 1)), moved(MoveOutcome::Reinforced));
+        assert_eq!(state.tile(1, 1), owned(0, 1));
+    }
+
+    #[test]
+    fn move_rejects_invalid_orders() {
+        let mut state = standard();
+        assert_eq!(state.apply(mv(0, (1, 1), (3, 3), 1)), Err(EngineError::NotAdjacent));
+        assert_eq!(state.apply(mv(0, (1, 1), (1, 1), 1)), Err(EngineError::NotAdjacent));
+        assert_eq!(state.apply(mv(0, (1, 1), (8, 1), 1)), Err(EngineError::OutOfBounds));
+        assert_eq!(state.apply(mv(0, (6, 6), (5, 5), 1)), Err(EngineError::NotYourTile));
+        assert_eq!(state.apply(mv(0, (1, 1), (2, 2), 2)), Err(EngineError::NotEnoughUnits));
+        assert_eq!(state.apply(mv(0, (1, 1), (2, 2), 0)), Err(EngineError::NotEnoughUnits));
+        assert_eq!(state.apply(mv(4, (1, 1), (2, 2), 1)), Err(EngineError::InvalidPlayer));
+
+        state.players[0].is_alive = false;
+        assert_eq!(state.apply(mv(0, (1, 1), (2, 2), 1)), Err(EngineError::PlayerEliminated));
+    }
+
+    #[test]
+    fn combat_counts_defense_and_leaves_survivors() {
+        let mut state = standard();
+        set(&mut state, 1, 1, owned(0, 7));
+        set(&mut state, 2, 2, TileState::Owned { player: 1, units: 1, has_defense: true, has_mine: false });
+
+        // 2 attackers against 1 + 2 defense bonus
+        assert_eq!(state.apply(mv(0, (1, 1), (2, 2), 2)), moved(MoveOutcome::Repelled { defenders_left: 1 }));
+        assert_eq!(
+            state.tile(2, 2),
+            TileState::Owned { player: 1, units: 1, has_defense: true, has_mine: false }
+        );
+
+        assert_eq!(
+            state.apply(mv(0, (1, 1), (2, 2), 5)),
+            moved(MoveOutcome::Won { defender: 1, attackers_left: 2 })
+        );
+        assert_eq!(state.tile(2, 2), owned(0, 2));
+        assert_eq!(state.players[0].score, 4 * CLAIM_SCORE + CAPTURE_SCORE);
+    }
+
+    #[test]
+    fn diplomacy_gates_attacks() {
+        let mut state = standard();
+        set(&mut state, 2, 2, owned(1, 1));
+
+        state.diplomacy[0][1] = DiplomacyState::Peace;
+        state.diplomacy[1][0] = DiplomacyState::Peace;
+        assert_eq!(state.apply(mv(0, (1, 1), (2, 2), 1)), Err(EngineError::AtPeace));
+
+        state.diplomacy[0][1] = DiplomacyState::War;
+        state.diplomacy[1][0] = DiplomacyState::War;
+        state.truce_until[0][1] = 100;
+        state.now = 99;
+        assert_eq!(state.apply(mv(0, (1, 1), (2, 2), 1)), Err(EngineError::TreatyCooldown));
+        state.now = 100;
+        assert!(state.apply(mv(0, (1, 1), (2, 2), 1)).is_ok());
+    }
+
+    #[test]
+    fn allies_reinforce_and_cross_each_others_tiles() {
+        let mut state = standard();
+        state.diplomacy[0][1] = DiplomacyState::Alliance;
+        state.diplomacy[1][0] = DiplomacyState::Alliance;
+        set(&mut state, 1, 1, owned(0, 3));
+        set(&mut state, 2, 1, owned(1, 4));
+        let (units_0, units_1) = (state.players[0].units, state.players[1].units);
+
+        // Reinforcing hands the units over to the ally
+        assert_eq!(state.apply(mv(0, (1, 1), (2, 1), 2)), moved(MoveOutcome::Reinforced));
+        assert_eq!(state.tile(2, 1), owned(1, 6));
+        assert_eq!(state.tile(1, 1), owned(0, 1));
+        assert_eq!(state.players[0].units, units_0 - 2);
+        assert_eq!(state.players[1].units, units_1 + 2);
+
+        // The ally's cap and the stack limit still apply
+        state.balance.max_units = state.players[1].units;
+        assert_eq!(state.apply(mv(0, (1, 1), (2, 1), 1)), Err(EngineError::MaxUnitsReached));
+        state.balance.max_units = u8::MAX;
+        set(&mut state, 2, 1, owned(1, u8::MAX));
+        assert_eq!(state.apply(mv(0, (1, 1), (2, 1), 1)), Err(EngineError::StackFull));
+
+        // Crossing the ally's tile reaches the camp behind it
+        set(&mut state, 2, 1, owned(1, 4));
+        set(&mut state, 1, 1, owned(0, 3));
+        assert_eq!(state.apply(mv(0, (1, 1), (3, 1), 3)), moved(MoveOutcome::Repelled { defenders_left: 1 }));
+        assert_eq!(state.tile(2, 1), owned(1, 4), "the crossed stack is untouched");
+
+        set(&mut state, 1, 1, owned(0, 2));
+        assert_eq!(state.apply(mv(0, (1, 1), (3, 3), 1)), Err(EngineError::NotAdjacent), "not a straight line");
+        assert_eq!(state.apply(mv(0, (1, 0), (3, 0), 1)), Err(EngineError::NotAdjacent), "middle tile is empty");
+
+        // At war the same jump is refused
+        state.diplomacy[0][1] = DiplomacyState::War;
+        state.diplomacy[1][0] = DiplomacyState::War;
+        assert_eq!(state.apply(mv(0, (1, 1), (3, 1), 1)), Err(EngineError::NotAdjacent));
+    }
+
+    #[test]
+    fn teammates_stack_units_on_one_tile() {
+        let mut state = GameState::new(VictoryCondition::Conquest, GameMode::Standard);
+        state.join(0, (0, 0)).unwrap();
+        state.join(1, (6, 0)).unwrap();
+        state.join(0, (0, 6)).unwrap();
+        state.join(1, (6, 6)).unwrap();
+        state.start().unwrap();
+        set(&mut state, 0, 2, owned(0, 3));
+        set(&mut state, 0, 3, owned(2, 2));
+
+        assert_eq!(state.apply(mv(0, (0, 2), (0, 3), 3)), moved(MoveOutcome::Reinforced));
+        assert_eq!(state.tile(0, 3), owned(2, 5));
+        assert_eq!(state.apply(mv(2, (0, 3), (0, 2), 4)), moved(MoveOutcome::Reinforced));
+        assert_eq!(state.tile(0, 2), owned(0, 4));
+        assert_eq!(state.tile(0, 3), owned(2, 1));
+        assert_eq!(state.players[0].units, INITIAL_UNITS + 1);
+        assert_eq!(state.players[2].units, INITIAL_UNITS - 1);
+    }
+
+    #[test]
+    fn stacks_cannot_overflow() {
+        let mut state = standard();
+        state.balance.max_units = u8::MAX;
+        state.players[0].gold = 1_000_000;
+        set(&mut state, 0, 0, owned(0, 250));
+        set(&mut state, 1, 0, owned(0, 10));
+
+        assert_eq!(state.apply(mv(0, (1, 0), (0, 0), 10)), Err(EngineError::StackFull));
+        assert_eq!(state.tile(1, 0), owned(0, 10), "a rejected move leaves the board alone");
+        assert_eq!(
+            state.apply(Action::TrainUnits { player: 0, x: 0, y: 0, count: 10 }),
+            Err(EngineError::StackFull)
+        );
+        assert!(state.apply(mv(0, (1, 0), (0, 0), 5)).is_ok());
+        assert_eq!(state.tile(0, 0), owned(0, 255));
+    }
+
+    #[test]
+    fn camps_pay_out_when_taken() {
+        let mut state = standard();
+        set(&mut state, 2, 1, owned(0, 5));
+        assert_eq!(state.apply(mv(0, (2, 1), (3, 1), 2)), moved(MoveOutcome::Repelled { defenders_left: 1 }));
+        assert_eq!(state.tile(3, 1), TileState::Neutral { units: 1, reward: CampReward::Gold { amount: 150 } });
+
+        assert_eq!(
+            state.apply(mv(0, (2, 1), (3, 1), 3)),
+            moved(MoveOutcome::CampCaptured { attackers_left: 2, reward: CampReward::Gold { amount: 150 } })
+        );
+        assert_eq!(state.players[0].gold, INITIAL_GOLD + 150);
+        assert_eq!(state.tile(3, 1), owned(0, 2));
+
+        // Unit camps add to the stack and the player's army
+        set(&mut state, 4, 1, TileState::Neutral { units: 1, reward: CampReward::Units { count: 4 } });
+        assert_eq!(
+            state.apply(mv(0, (3, 1), (4, 1), 2)),
+            moved(MoveOutcome::CampCaptured { attackers_left: 5, reward: CampReward::Units { count: 4 } })
+        );
+        assert_eq!(state.players[0].units, INITIAL_UNITS + 4);
+    }
+
+    #[test]
+    fn resources_become_mines() {
+        let mut state = standard();
+        set(&mut state, 2, 2, owned(0, 1));
+        assert_eq!(
+            state.apply(mv(0, (2, 2), (3, 3), 1)),
+            moved(MoveOutcome::ResourceClaimed { resource_type: ResourceType::Gold, amount: 500 })
+        );
+        assert_eq!(state.players[0].gold, INITIAL_GOLD + 500);
+        assert_eq!(state.tile(3, 3), TileState::Owned { player: 0, units: 1, has_defense: false, has_mine: true });
+
+        // Six tiles at 5 gold and wood each, plus double gold from the mine
+        assert_eq!(
+            state.apply(Action::CollectResources { player: 0 }),
+            Ok(Outcome::Collected { gold: 40, wood: 30 })
+        );
+        assert_eq!(state.players[0].wood, INITIAL_WOOD + 30);
+    }
+
+    #[test]
+    fn build_defense_spends_wood() {
+        let mut state = standard();
+        let build = |x, y| Action::BuildDefense { player: 0, x, y };
+        assert_eq!(state.apply(build(6, 6)), Err(EngineError::NotYourTile));
+        assert_eq!(state.apply(build(0, 0)), Ok(Outcome::DefenseBuilt));
+        assert_eq!(state.players[0].wood, INITIAL_WOOD - DEFENSE_COST_WOOD);
+        assert_eq!(state.players[0].score, 4 * CLAIM_SCORE + DEFENSE_SCORE);
+
+        state.players[0].wood = 100;
+        assert_eq!(state.apply(build(0, 0)), Err(EngineError::AlreadyHasDefense));
+        state.players[0].wood = 0;
+        assert_eq!(state.apply(build(1, 0)), Err(EngineError::NotEnoughResources));
+    }
+
+    #[test]
+    fn train_units_respects_gold_and_the_unit_cap() {
+        let mut state = standard();
+        let train = |count| Action::TrainUnits { player: 0, x: 0, y: 0, count };
+        assert_eq!(state.apply(train(2)), Ok(Outcome::UnitsTrained { gold_spent: 2 * UNIT_COST_GOLD }));
+        assert_eq!(state.tile(0, 0), owned(0, 3));
+        assert_eq!(state.players[0].units, INITIAL_UNITS + 2);
+        assert_eq!(state.apply(train(3)), Err(EngineError::NotEnoughResources));
+
+        state.players[0].gold = 10_000;
+        assert_eq!(state.apply(train(MAX_UNITS)), Err(EngineError::MaxUnitsReached));
+        assert_eq!(
+            state.apply(Action::TrainUnits { player: 0, x: 6, y: 6, count: 1 }),
+            Err(EngineError::NotYourTile)
+        );
+
+        // A preset balance replaces the stock prices
+        state.balance.unit_cost_gold = 1;
+        assert_eq!(state.apply(train(1)), Ok(Outcome::UnitsTrained { gold_spent: 1 }));
+    }
+
+    #[test]
+    fn storm_collapses_rings_until_the_center() {
+        let mut state = standard();
+        assert_eq!(state.apply(Action::AdvanceStorm), Err(EngineError::WrongGameMode));
+
+        let mut state = duel(VictoryCondition::Conquest, GameMode::BattleRoyale { shrink_interval_slots: 10 });
+        assert_eq!(
+            state.apply(Action::AdvanceStorm),
+            Ok(Outcome::StormAdvanced { ring: 0, units_destroyed: 6, eliminated: [false; 4] })
+        );
+        assert_eq!(state.tile(0, 0), TileState::Collapsed);
+        assert_eq!(state.players[0].units, 0);
+        assert_eq!(state.apply(mv(0, (1, 1), (0, 0), 1)), Err(EngineError::TileCollapsed));
+
+        assert_eq!(
+            state.apply(Action::AdvanceStorm),
+            Ok(Outcome::StormAdvanced { ring: 1, units_destroyed: 2, eliminated: [true, true, false, false] })
+        );
+        assert_eq!(state.victory(), Some(None), "nobody survived");
+
+        assert!(state.apply(Action::AdvanceStorm).is_ok());
+        assert_eq!(state.apply(Action::AdvanceStorm), Err(EngineError::StormComplete));
+    }
+
+    #[test]
+    fn barbarians_hit_the_leaders_weakest_tile() {
+        let mut state = standard();
+        set(&mut state, 5, 5, owned(1, 5));
+
+        assert_eq!(
+            state.apply(Action::BarbarianWave),
+            Ok(Outcome::BarbariansAttacked { target: 1, x: 6, y: 6, strength: 3, defense: 1, overrun: true })
+        );
+        assert_eq!(
+            state.tile(6, 6),
+            TileState::Neutral { units: 2, reward: CampReward::Gold { amount: BARBARIAN_REWARD_GOLD } }
+        );
+
+        // A fortified tile absorbs the wave: the defense breaks, the garrison holds
+        for (x, y) in [(5, 6), (7, 6), (6, 7), (7, 7)] {
+            set(&mut state, x, y, TileState::Owned { player: 1, units: 4, has_defense: true, has_mine: false });
+        }
+        set(&mut state, 5, 5, TileState::Owned { player: 1, units: 2, has_defense: true, has_mine: false });
+        assert_eq!(
+            state.apply(Action::BarbarianWave),
+            Ok(Outcome::BarbariansAttacked { target: 1, x: 5, y: 5, strength: 4, defense: 4, overrun: false })
+        );
+        assert_eq!(state.tile(5, 5), owned(1, 1));
+    }
+
+    #[test]
+    fn conquest_ends_with_one_team_standing() {
+        let mut state = standard();
+        assert_eq!(state.victory(), None);
+        for (x, y) in [(6, 6), (7, 6), (6, 7), (7, 7)] {
+            set(&mut state, x, y, TileState::Empty);
+        }
+        assert_eq!(state.eliminate_landless(), [false, true, false, false]);
+        assert_eq!(state.victory(), Some(Some(0)));
+    }
+
+    #[test]
+    fn domination_needs_the_hold_for_enough_turns() {
+        let mut state = duel(VictoryCondition::Domination { percent: 10, ticks: 2 }, GameMode::Standard);
+        // 10% of 64 tiles rounds up to 7
+        set(&mut state, 2, 0, owned(0, 1));
+        set(&mut state, 2, 1, owned(0, 2));
+        assert_eq!(state.apply(mv(0, (2, 1), (2, 2), 1)), moved(MoveOutcome::Claimed));
+        assert_eq!((state.control_team, state.control_since), (Some(0), 1));
+        assert_eq!(state.victory(), None);
+
+        state.apply(mv(0, (2, 2), (2, 1), 1)).unwrap();
+        assert_eq!(state.victory(), None);
+        state.apply(mv(0, (2, 1), (2, 2), 1)).unwrap();
+        assert_eq!(state.victory(), Some(Some(0)));
+    }
+
+    #[test]
+    fn king_of_the_hill_needs_every_center_tile() {
+        let mut state = duel(VictoryCondition::KingOfTheHill { ticks: 1 }, GameMode::Standard);
+        for (x, y) in CENTER_TILES {
+            set(&mut state, x as u8, y as u8, owned(0, 1));
+        }
+        set(&mut state, 4, 4, owned(1, 1));
+        state.apply(Action::CollectResources { player: 0 }).unwrap();
+        state.apply(mv(0, (1, 1), (2, 2), 1)).unwrap();
+        assert_eq!(state.control_team, None);
+
+        set(&mut state, 4, 4, owned(0, 1));
+        state.apply(mv(0, (2, 2), (3, 3), 1)).unwrap();
+        assert_eq!(state.victory(), None);
+        state.apply(mv(0, (3, 3), (2, 2), 1)).unwrap();
+        assert_eq!(state.victory(), Some(Some(0)));
+    }
+
+    #[test]
+    fn score_race_and_timed_pick_the_score_leader() {
+        let mut state = duel(VictoryCondition::ScoreRace { target: 4 * CLAIM_SCORE + 20 }, GameMode::Standard);
+        state.apply(mv(0, (1, 1), (2, 2), 1)).unwrap();
+        assert_eq!(state.victory(), None);
+        state.apply(mv(0, (1, 0), (2, 1), 1)).unwrap();
+        assert_eq!(state.victory(), Some(Some(0)));
+
+        let mut state = duel(VictoryCondition::Timed { deadline: 100 }, GameMode::Standard);
+        state.now = 99;
+        assert_eq!(state.victory(), None);
+        state.now = 100;
+        assert_eq!(state.victory(), Some(None), "equal scores draw");
+        state.apply(mv(1, (6, 6), (5, 5), 1)).unwrap();
+        assert_eq!(state.victory(), Some(Some(1)));
+    }
+
+    #[test]
+    fn flags_are_carried_home_for_a_capture() {
+        let mut state = duel(VictoryCondition::Conquest, GameMode::CaptureTheFlag { captures_to_win: 1 });
+        assert_eq!(state.flags[1].position(), (7, 7));
+
+        // Take the enemy flag tile
+        set(&mut state, 7, 6, owned(0, 5));
+        assert!(state.apply(mv(0, (7, 6), (7, 7), 4)).is_ok());
+        assert_eq!(state.flags[1].carrier, Some(0));
+
+        // Walk it home along a corridor of our own tiles
+        state.flags[1].x = 1;
+        state.flags[1].y = 1;
+        set(&mut state, 1, 1, owned(0, 2));
+        assert_eq!(
+            state.apply(mv(0, (1, 1), (0, 0), 2)),
+            Ok(Outcome::Moved { outcome: MoveOutcome::Reinforced, flag_captured: true })
+        );
+        assert_eq!(state.captures[0], 1);
+        assert_eq!(state.flags[1].position(), (7, 7));
+        assert_eq!(state.flags[1].carrier, None);
+        assert_eq!(state.victory(), Some(Some(0)));
+    }
+
+    #[test]
+    fn flag_drops_only_with_the_carrying_stack() {
+        let mut state = duel(VictoryCondition::Conquest, GameMode::CaptureTheFlag { captures_to_win: 3 });
+        state.flags[1] = Flag { home_x: 7, home_y: 7, x: 1, y: 1, carrier: Some(0) };
+        set(&mut state, 1, 1, owned(0, 5));
+        set(&mut state, 2, 2, owned(1, 5));
+
+        // Part of the stack fails an attack; the rest still carries the flag
+        assert!(state.apply(mv(0, (1, 1), (2, 2), 2)).is_ok());
+        assert_eq!(state.flags[1].carrier, Some(0));
+        assert_eq!(state.flags[1].position(), (1, 1));
+
+        // The whole remaining stack dies; the flag is left on the tile
+        assert!(state.apply(mv(0, (1, 1), (2, 2), 3)).is_ok());
+        assert_eq!(state.flags[1].carrier, None);
+        assert_eq!(state.flags[1].position(), (1, 1));
+    }
+
+    #[test]
+    fn autoplay_only_plans_legal_actions_and_is_deterministic() {
+        let presets =
+            [StrategyMode::Aggressive, StrategyMode::Defensive, StrategyMode::Balanced, StrategyMode::Economic];
+        let run = |mode: StrategyMode| {
+            let mut state = standard();
+            for tick in 0..60 {
+                for pi in 0..2 {
+                    let action = state.autoplay_action(pi, &mode.profile(), tick);
+                    assert_eq!(state.apply(action).err(), None, "{:?} tick {}: {:?}", mode, tick, action);
+                }
+            }
+            state
+        };
+        for mode in presets {
+            assert_eq!(run(mode), run(mode));
+        }
+    }
+}
@@ -0,0 +1,597 @@
+//! Headless bot-vs-bot tournaments on top of `ghostnet-engine`.
+//!
+//! Bots implement [`Bot`] and are seated on seeded maps; [`run_tournament`]
+//! plays every game to completion and aggregates a [`Report`] that can be
+//! written as JSON or CSV.
+
+use std::fmt::Write as _;
+
+use ghostnet_engine::{
+    Action, Balance, CampReward, EngineError, GameMode, GameState, StrategyMode, StrategyProfile, TileState,
+    VictoryCondition, GRID_SIZE, MAX_PLAYERS,
+};
+
+/// Free-for-all spawn corners by seat, same as the program's `TeamLayout::FreeForAll`
+pub const SPAWN_CORNERS: [(u8, u8); MAX_PLAYERS as usize] = [(0, 0), (6, 0), (0, 6), (6, 6)];
+
+/// Names accepted by [`bot_by_name`]
+pub const BUILTIN_BOTS: [&str; 5] = ["aggressive", "defensive", "balanced", "economic", "random"];
+
+// ==================== RNG ====================
+
+/// SplitMix64: small, fast and stable across platforms so seeds reproduce
+#[derive(Clone, Debug)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `0..n`; `n` must be non-zero
+    pub fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            items.swap(i, self.below(i as u64 + 1) as usize);
+        }
+    }
+}
+
+// ==================== BOTS ====================
+
+/// An order a bot gives for its own seat
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Order {
+    Move { from: (u8, u8), to: (u8, u8), count: u8 },
+    BuildDefense { x: u8, y: u8 },
+    TrainUnits { x: u8, y: u8, count: u8 },
+    CollectResources,
+}
+
+impl Order {
+    pub fn action(self, player: u8) -> Action {
+        match self {
+            Order::Move { from, to, count } => Action::Move { player, from, to, count },
+            Order::BuildDefense { x, y } => Action::BuildDefense { player, x, y },
+            Order::TrainUnits { x, y, count } => Action::TrainUnits { player, x, y, count },
+            Order::CollectResources => Action::CollectResources { player },
+        }
+    }
+
+    /// The order behind a player action; None for cranks
+    pub fn from_action(action: Action) -> Option<Self> {
+        match action {
+            Action::Move { from, to, count, .. } => Some(Order::Move { from, to, count }),
+            Action::BuildDefense { x, y, .. } => Some(Order::BuildDefense { x, y }),
+            Action::TrainUnits { x, y, count, .. } => Some(Order::TrainUnits { x, y, count }),
+            Action::CollectResources { .. } => Some(Order::CollectResources),
+            Action::AdvanceStorm | Action::BarbarianWave => None,
+        }
+    }
+}
+
+/// What a bot sees on its turn: the full board and its own seat
+pub struct View<'a> {
+    pub state: &'a GameState,
+    pub me: u8,
+    /// Orders the simulator will accept this turn; extra orders are dropped
+    pub max_orders: usize,
+}
+
+pub trait Bot {
+    fn name(&self) -> &str;
+
+    /// Orders for this turn, applied in sequence. Rejected orders are counted and skipped.
+    fn decide(&mut self, view: &View) -> Vec<Order>;
+}
+
+/// Plays the engine's autoplay policy with a strategy profile, exactly like `autoplay_tick`
+pub struct AutoplayBot {
+    name: String,
+    profile: StrategyProfile,
+    tick: u64,
+}
+
+impl AutoplayBot {
+    pub fn new(name: impl Into<String>, profile: StrategyProfile) -> Self {
+        AutoplayBot { name: name.into(), profile, tick: 0 }
+    }
+}
+
+impl Bot for AutoplayBot {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn decide(&mut self, view: &View) -> Vec<Order> {
+        // Plan against a scratch copy so later orders see the earlier ones
+        let mut scratch = view.state.clone();
+        let mut orders = Vec::with_capacity(view.max_orders);
+        while orders.len() < view.max_orders {
+            let action = scratch.autoplay_action(view.me, &self.profile, self.tick);
+            self.tick += 1;
+            let Some(order) = Order::from_action(action) else { break };
+            if scratch.apply(action).is_err() {
+                break;
+            }
+            orders.push(order);
+        }
+        orders
+    }
+}
+
+/// Picks uniformly among plausible orders; a baseline the other bots should beat
+pub struct RandomBot {
+    rng: Rng,
+}
+
+impl RandomBot {
+    pub fn new(seed: u64) -> Self {
+        RandomBot { rng: Rng::new(seed) }
+    }
+}
+
+impl Bot for RandomBot {
+    fn name(&self) -> &str {
+        "random"
+    }
+
+    fn decide(&mut self, view: &View) -> Vec<Order> {
+        let state = view.state;
+        let player = &state.players[view.me as usize];
+        let balance = &state.balance;
+        let mut candidates = vec![Order::CollectResources];
+        for (x, y, units, has_defense) in state.owned_tiles(view.me) {
+            if units > 0 {
+                for (nx, ny) in GameState::neighbors(x, y) {
+                    let count = 1 + self.rng.below(units as u64) as u8;
+                    candidates.push(Order::Move { from: (x, y), to: (nx, ny), count });
+                }
+            }
+            if !has_defense && player.wood >= balance.defense_cost_wood {
+                candidates.push(Order::BuildDefense { x, y });
+            }
+            if player.gold >= balance.unit_cost_gold && player.units < balance.max_units {
+                candidates.push(Order::TrainUnits { x, y, count: 1 });
+            }
+        }
+        (0..view.max_orders)
+            .map(|_| candidates[self.rng.below(candidates.len() as u64) as usize])
+            .collect()
+    }
+}
+
+/// Build a built-in bot by name; `seed` feeds bots that use randomness
+pub fn bot_by_name(name: &str, seed: u64) -> Option<Box<dyn Bot>> {
+    let mode = match name {
+        "aggressive" => StrategyMode::Aggressive,
+        "defensive" => StrategyMode::Defensive,
+        "balanced" => StrategyMode::Balanced,
+        "economic" => StrategyMode::Economic,
+        "random" => return Some(Box::new(RandomBot::new(seed))),
+        _ => return None,
+    };
+    Some(Box::new(AutoplayBot::new(name, mode.profile())))
+}
+
+// ==================== MATCHES ====================
+
+#[derive(Clone, Debug)]
+pub struct SimConfig {
+    pub games: u32,
+    pub seed: u64,
+    /// Rounds after which the territory leader wins, as with `end_game`
+    pub max_rounds: u32,
+    /// Orders each seat may give per round
+    pub orders_per_round: usize,
+    /// Sets of four rotationally symmetric neutral camps scattered on top of the fixed map
+    pub extra_camps: u8,
+    /// Economy every game is played with, for trying balance changes before publishing a preset
+    pub balance: Balance,
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        SimConfig {
+            games: 1000,
+            seed: 0,
+            max_rounds: 200,
+            orders_per_round: 3,
+            extra_camps: 1,
+            balance: Balance::default(),
+        }
+    }
+}
+
+/// How one bot fared in one game
+#[derive(Clone, Debug)]
+pub struct Seat {
+    /// Index into the tournament's bot list
+    pub bot: usize,
+    pub seat: u8,
+    pub score: u64,
+    pub tiles: u32,
+    pub alive: bool,
+    pub rejected_orders: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct MatchResult {
+    pub game: u32,
+    pub seed: u64,
+    pub rounds: u32,
+    /// Bot index of the winner, None on a draw
+    pub winner: Option<usize>,
+    /// Whether the victory condition decided the game rather than the round limit
+    pub decided: bool,
+    pub seats: Vec<Seat>,
+}
+
+/// Seeded free-for-all map: the fixed mines and camps plus `extra_camps` symmetric camp sets
+pub fn seeded_map(players: u8, extra_camps: u8, balance: Balance, rng: &mut Rng) -> Result<GameState, EngineError> {
+    let mut state = GameState::new(VictoryCondition::Conquest, GameMode::Standard);
+    state.balance = balance;
+    for seat in 0..players {
+        state.join(seat, SPAWN_CORNERS[seat as usize])?;
+    }
+    state.start()?;
+
+    let last = GRID_SIZE - 1;
+    let rotations = |(x, y): (u8, u8)| [(x, y), (last - y, x), (last - x, last - y), (y, last - x)];
+    let mut placed = 0;
+    let mut attempts = 0;
+    while placed < extra_camps && attempts < 64 {
+        attempts += 1;
+        let origin = (rng.below(GRID_SIZE as u64) as u8, rng.below(GRID_SIZE as u64) as u8);
+        let spots = rotations(origin);
+        if !spots.iter().all(|&(x, y)| state.tile(x, y) == TileState::Empty) {
+            continue;
+        }
+        let amount = 50 + 25 * rng.below(5);
+        let reward = match rng.below(3) {
+            0 => CampReward::Gold { amount },
+            1 => CampReward::Wood { amount },
+            _ => CampReward::Units { count: 1 + rng.below(3) as u8 },
+        };
+        let units = 1 + rng.below(5) as u8;
+        for (x, y) in spots {
+            state.grid[y as usize][x as usize] = TileState::Neutral { units, reward };
+        }
+        placed += 1;
+    }
+    Ok(state)
+}
+
+/// Play one game between `bots` (2 to 4) on the map for `seed`
+pub fn play_match(config: &SimConfig, bots: &mut [Box<dyn Bot>], game: u32, seed: u64) -> Result<MatchResult, EngineError> {
+    let mut rng = Rng::new(seed);
+    let players = bots.len() as u8;
+
+    // Random seating so no bot keeps the same corner or turn order
+    let mut seating: Vec<usize> = (0..bots.len()).collect();
+    rng.shuffle(&mut seating);
+    let mut state = seeded_map(players, config.extra_camps, config.balance, &mut rng)?;
+
+    let mut rejected = vec![0u32; bots.len()];
+    let mut rounds = 0;
+    let mut outcome = None;
+    while rounds < config.max_rounds && outcome.is_none() {
+        // Rotate who moves first each round
+        for k in 0..players {
+            let seat = (rounds as u8 + k) % players;
+            if !state.players[seat as usize].is_alive {
+                continue;
+            }
+            state.now = rounds as i64;
+            let bot = seating[seat as usize];
+            let view = View { state: &state, me: seat, max_orders: config.orders_per_round };
+            let orders = bots[bot].decide(&view);
+            for order in orders.into_iter().take(config.orders_per_round) {
+                if state.apply(order.action(seat)).is_err() {
+                    rejected[bot] += 1;
+                }
+            }
+            state.eliminate_landless();
+            outcome = state.victory();
+            if outcome.is_some() {
+                break;
+            }
+        }
+        rounds += 1;
+    }
+
+    let decided = outcome.is_some();
+    let winning_team = outcome.unwrap_or_else(|| state.territory_leader());
+    let seats = (0..players)
+        .map(|seat| {
+            let bot = seating[seat as usize];
+            let player = &state.players[seat as usize];
+            Seat {
+                bot,
+                seat,
+                score: player.score,
+                tiles: state.tiles_owned(seat),
+                alive: player.is_alive,
+                rejected_orders: rejected[bot],
+            }
+        })
+        .collect();
+    Ok(MatchResult {
+        game,
+        seed,
+        rounds,
+        // Free-for-all: team == seat
+        winner: winning_team.map(|team| seating[team as usize]),
+        decided,
+        seats,
+    })
+}
+
+// ==================== TOURNAMENT ====================
+
+#[derive(Clone, Debug, Default)]
+pub struct BotStats {
+    pub label: String,
+    pub games: u32,
+    pub wins: u32,
+    pub draws: u32,
+    pub eliminations: u32,
+    pub rejected_orders: u64,
+    pub scores: Vec<u64>,
+    pub tiles: Vec<u32>,
+}
+
+impl BotStats {
+    pub fn win_rate(&self) -> f64 {
+        ratio(self.wins as f64, self.games as f64)
+    }
+
+    pub fn average_score(&self) -> f64 {
+        ratio(self.scores.iter().sum::<u64>() as f64, self.scores.len() as f64)
+    }
+
+    pub fn average_tiles(&self) -> f64 {
+        ratio(self.tiles.iter().map(|&t| t as u64).sum::<u64>() as f64, self.tiles.len() as f64)
+    }
+
+    /// Nearest-rank percentile of final scores
+    pub fn score_percentile(&self, percent: u32) -> u64 {
+        let mut sorted = self.scores.clone();
+        sorted.sort_unstable();
+        match sorted.len() {
+            0 => 0,
+            n => sorted[((n * percent as usize).div_ceil(100)).clamp(1, n) - 1],
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Report {
+    pub config: SimConfig,
+    pub bots: Vec<BotStats>,
+    pub matches: Vec<MatchResult>,
+}
+
+impl Report {
+    pub fn draws(&self) -> u32 {
+        self.matches.iter().filter(|m| m.winner.is_none()).count() as u32
+    }
+
+    pub fn average_rounds(&self) -> f64 {
+        ratio(self.matches.iter().map(|m| m.rounds as u64).sum::<u64>() as f64, self.matches.len() as f64)
+    }
+
+    /// Share of games settled by the victory condition rather than the round limit
+    pub fn decided_rate(&self) -> f64 {
+        ratio(self.matches.iter().filter(|m| m.decided).count() as f64, self.matches.len() as f64)
+    }
+
+    /// Summary with balance constants, per-bot win rates and score distributions
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        let c = &self.config;
+        let _ = writeln!(out, "{{");
+        let _ = writeln!(
+            out,
+            "  \"config\": {{\"games\": {}, \"seed\": {}, \"max_rounds\": {}, \"orders_per_round\": {}, \"extra_camps\": {}}},",
+            c.games, c.seed, c.max_rounds, c.orders_per_round, c.extra_camps
+        );
+        let b = &c.balance;
+        let _ = writeln!(
+            out,
+            "  \"balance\": {{\"initial_gold\": {}, \"initial_wood\": {}, \"unit_cost_gold\": {}, \
+             \"defense_cost_wood\": {}, \"resource_per_tick\": {}, \"max_units\": {}}},",
+            b.initial_gold, b.initial_wood, b.unit_cost_gold, b.defense_cost_wood, b.resource_per_tick, b.max_units
+        );
+        let _ = writeln!(
+            out,
+            "  \"games\": {}, \"draws\": {}, \"decided_rate\": {:.4}, \"average_rounds\": {:.2},",
+            self.matches.len(),
+            self.draws(),
+            self.decided_rate(),
+            self.average_rounds()
+        );
+        let _ = writeln!(out, "  \"bots\": [");
+        for (i, bot) in self.bots.iter().enumerate() {
+            let _ = writeln!(
+                out,
+                "    {{\"bot\": \"{}\", \"games\": {}, \"wins\": {}, \"draws\": {}, \"win_rate\": {:.4}, \
+                 \"eliminations\": {}, \"rejected_orders\": {}, \"average_tiles\": {:.2}, \"score\": {{\"mean\": {:.2}, \
+                 \"min\": {}, \"p25\": {}, \"median\": {}, \"p75\": {}, \"max\": {}}}}}{}",
+                escape_json(&bot.label),
+                bot.games,
+                bot.wins,
+                bot.draws,
+                bot.win_rate(),
+                bot.eliminations,
+                bot.rejected_orders,
+                bot.average_tiles(),
+                bot.average_score(),
+                bot.score_percentile(0),
+                bot.score_percentile(25),
+                bot.score_percentile(50),
+                bot.score_percentile(75),
+                bot.score_percentile(100),
+                if i + 1 < self.bots.len() { "," } else { "" }
+            );
+        }
+        let _ = writeln!(out, "  ]");
+        let _ = writeln!(out, "}}");
+        out
+    }
+
+    /// One row per bot per game, for loading into a spreadsheet or dataframe
+    pub fn to_csv(&self) -> String {
+        let mut out = String::from("game,seed,rounds,decided,bot,seat,won,draw,alive,score,tiles,rejected_orders\n");
+        for m in &self.matches {
+            for seat in &m.seats {
+                let _ = writeln!(
+                    out,
+                    "{},{},{},{},{},{},{},{},{},{},{},{}",
+                    m.game,
+                    m.seed,
+                    m.rounds,
+                    m.decided,
+                    escape_csv(&self.bots[seat.bot].label),
+                    seat.seat,
+                    m.winner == Some(seat.bot),
+                    m.winner.is_none(),
+                    seat.alive,
+                    seat.score,
+                    seat.tiles,
+                    seat.rejected_orders
+                );
+            }
+        }
+        out
+    }
+}
+
+/// Play `config.games` games between the named bots (2 to 4 of them)
+pub fn run_tournament(config: &SimConfig, names: &[String]) -> Result<Report, String> {
+    if !(2..=MAX_PLAYERS as usize).contains(&names.len()) {
+        return Err(format!("need 2 to {} bots, got {}", MAX_PLAYERS, names.len()));
+    }
+    if !config.balance.is_valid() {
+        return Err("balance needs a non-zero unit cost and room for the starting units".to_string());
+    }
+    if let Some(unknown) = names.iter().find(|name| bot_by_name(name, 0).is_none()) {
+        return Err(format!("unknown bot '{}' (expected one of {})", unknown, BUILTIN_BOTS.join(", ")));
+    }
+
+    // Repeated bots get their list position so their stats stay apart
+    let mut bots: Vec<BotStats> = names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            let repeated = names.iter().filter(|other| *other == name).count() > 1;
+            let label = if repeated { format!("{}#{}", name, i) } else { name.clone() };
+            BotStats { label, ..BotStats::default() }
+        })
+        .collect();
+
+    let mut matches = Vec::with_capacity(config.games as usize);
+    let mut seeds = Rng::new(config.seed);
+    for game in 0..config.games {
+        let seed = seeds.next_u64();
+        let mut players: Vec<Box<dyn Bot>> = names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| bot_by_name(name, seed.wrapping_add(i as u64 + 1)).expect("validated above"))
+            .collect();
+        let result = play_match(config, &mut players, game, seed).map_err(|e| e.to_string())?;
+
+        for seat in &result.seats {
+            let stats = &mut bots[seat.bot];
+            stats.games += 1;
+            match result.winner {
+                Some(winner) if winner == seat.bot => stats.wins += 1,
+                Some(_) => {}
+                None => stats.draws += 1,
+            }
+            stats.eliminations += !seat.alive as u32;
+            stats.rejected_orders += seat.rejected_orders as u64;
+            stats.scores.push(seat.score);
+            stats.tiles.push(seat.tiles);
+        }
+        matches.push(result);
+    }
+
+    Ok(Report { config: config.clone(), bots, matches })
+}
+
+fn ratio(numerator: f64, denominator: f64) -> f64 {
+    if denominator == 0.0 {
+        0.0
+    } else {
+        numerator / denominator
+    }
+}
+
+fn escape_json(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn escape_csv(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+// ==================== TESTS ====================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bots(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    fn config(seed: u64) -> SimConfig {
+        SimConfig { games: 12, seed, max_rounds: 60, ..SimConfig::default() }
+    }
+
+    #[test]
+    fn same_seed_replays_the_same_tournament() {
+        let names = bots(&["aggressive", "economic", "balanced", "random"]);
+        let first = run_tournament(&config(7), &names).unwrap();
+        let second = run_tournament(&config(7), &names).unwrap();
+        assert_eq!(first.to_csv(), second.to_csv());
+        assert_eq!(first.to_json(), second.to_json());
+
+        let other = run_tournament(&config(8), &names).unwrap();
+        assert_ne!(first.to_csv(), other.to_csv());
+    }
+
+    #[test]
+    fn results_add_up() {
+        let names = bots(&["defensive", "random"]);
+        let report = run_tournament(&config(3), &names).unwrap();
+        assert_eq!(report.matches.len(), 12);
+        let wins: u32 = report.bots.iter().map(|bot| bot.wins).sum();
+        assert_eq!(wins + report.draws(), 12);
+        assert!(report.bots.iter().all(|bot| bot.games == 12));
+    }
+
+    #[test]
+    fn rejects_bad_setups() {
+        assert!(run_tournament(&config(0), &bots(&["balanced"])).is_err());
+        assert!(run_tournament(&config(0), &bots(&["balanced", "nobody"])).is_err());
+        let broken = SimConfig { balance: Balance { unit_cost_gold: 0, ..Balance::default() }, ..config(0) };
+        assert!(run_tournament(&broken, &bots(&["balanced", "random"])).is_err());
+    }
+}
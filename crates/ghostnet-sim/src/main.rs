@@ -0,0 +1,100 @@
+//! ghostnet-sim: run bot-vs-bot tournaments and print win rates, game length
+//! and score distributions.
+//!
+//! ```text
+//! ghostnet-sim --bots aggressive,economic,balanced,random --games 5000 --seed 7 --format json
+//! ghostnet-sim --bots balanced,balanced --format csv --out games.csv
+//! ghostnet-sim --set unit_cost_gold=30 --set resource_per_tick=6
+//! ```
+
+use std::process::ExitCode;
+
+use ghostnet_sim::{run_tournament, SimConfig, BUILTIN_BOTS};
+
+const USAGE: &str = "usage: ghostnet-sim [--bots a,b[,c,d]] [--games N] [--seed N] [--max-rounds N]
+                    [--orders N] [--camps N] [--set BALANCE_FIELD=N]... [--format json|csv] [--out PATH]";
+
+enum Format {
+    Json,
+    Csv,
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("ghostnet-sim: {}", err);
+            eprintln!("{}", USAGE);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run() -> Result<(), String> {
+    let mut config = SimConfig::default();
+    let mut bots: Vec<String> = BUILTIN_BOTS[..4].iter().map(|s| s.to_string()).collect();
+    let mut format = Format::Json;
+    let mut out = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(flag) = args.next() {
+        if flag == "--help" || flag == "-h" {
+            println!("{}\n\nbots: {}", USAGE, BUILTIN_BOTS.join(", "));
+            return Ok(());
+        }
+        let value = args.next().ok_or_else(|| format!("{} needs a value", flag))?;
+        match flag.as_str() {
+            "--bots" => bots = value.split(',').map(|s| s.trim().to_string()).collect(),
+            "--games" => config.games = parse(&flag, &value)?,
+            "--seed" => config.seed = parse(&flag, &value)?,
+            "--max-rounds" => config.max_rounds = parse(&flag, &value)?,
+            "--orders" => config.orders_per_round = parse(&flag, &value)?,
+            "--camps" => config.extra_camps = parse(&flag, &value)?,
+            "--set" => set_balance(&mut config, &value)?,
+            "--format" => {
+                format = match value.as_str() {
+                    "json" => Format::Json,
+                    "csv" => Format::Csv,
+                    _ => return Err(format!("unknown format '{}'", value)),
+                }
+            }
+            "--out" => out = Some(value),
+            _ => return Err(format!("unknown flag '{}'", flag)),
+        }
+    }
+
+    let report = run_tournament(&config, &bots)?;
+    let rendered = match format {
+        Format::Json => report.to_json(),
+        Format::Csv => report.to_csv(),
+    };
+    match out {
+        Some(path) => std::fs::write(&path, rendered).map_err(|e| format!("writing {}: {}", path, e)),
+        None => {
+            print!("{}", rendered);
+            Ok(())
+        }
+    }
+}
+
+/// Apply one `field=value` balance override, e.g. `unit_cost_gold=30`
+fn set_balance(config: &mut SimConfig, assignment: &str) -> Result<(), String> {
+    let (field, value) = assignment
+        .split_once('=')
+        .ok_or_else(|| format!("--set expects field=value, got '{}'", assignment))?;
+    let b = &mut config.balance;
+    match field {
+        "initial_gold" => b.initial_gold = parse(field, value)?,
+        "initial_wood" => b.initial_wood = parse(field, value)?,
+        "unit_cost_gold" => b.unit_cost_gold = parse(field, value)?,
+        "defense_cost_wood" => b.defense_cost_wood = parse(field, value)?,
+        "resource_per_tick" => b.resource_per_tick = parse(field, value)?,
+        "max_units" => b.max_units = parse(field, value)?,
+        _ => return Err(format!("unknown balance field '{}'", field)),
+    }
+    Ok(())
+}
+
+fn parse<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("invalid value '{}' for {}", value, flag))
+}
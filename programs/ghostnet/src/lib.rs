@@ -2,95 +2,169 @@ use anchor_lang::prelude::*;
 
 declare_id!("9LuS7xu5DLUac1sbFsF2uBYAdnfJrrs1C2JHgdYfjmtQ");
 
+use ghostnet_engine as engine;
+pub use ghostnet_engine::{
+    BARBARIAN_BASE_STRENGTH, BARBARIAN_REWARD_GOLD, CAMP_CAPTURE_SCORE, CAMP_UNITS, CENTER_TILES,
+    CTF_CAPTURE_SCORE, DEFENSE_COST_WOOD, GRID_SIZE, INITIAL_GOLD, INITIAL_WOOD, MAX_PLAYERS, MAX_RISK_THRESHOLD,
+    MAX_STORM_RINGS, MAX_UNITS, MIN_RISK_THRESHOLD, RESOURCE_PER_TICK, UNIT_COST_GOLD,
+};
+
 // MagicBlock Delegation Program
 pub const DELEGATION_PROGRAM_ID: Pubkey = pubkey!("DELeGGvXpWV2fqJUhqcF5ZSYMS4JTLjteaAMARRSaeSh");
+// MagicBlock ER runtime, schedules commits and undelegations
+pub const MAGIC_PROGRAM_ID: Pubkey = pubkey!("Magic11111111111111111111111111111111111111");
+pub const MAGIC_CONTEXT_ID: Pubkey = pubkey!("MagicContext1111111111111111111111111111111");
+
+// Instruction discriminators for the MagicBlock programs
+pub const DELEGATE_DISCRIMINATOR: [u8; 8] = [0; 8];
+pub const SCHEDULE_COMMIT_DISCRIMINATOR: [u8; 4] = [1, 0, 0, 0];
+pub const SCHEDULE_COMMIT_AND_UNDELEGATE_DISCRIMINATOR: [u8; 4] = [2, 0, 0, 0];
 
 // Seeds
 pub const GAME_SEED: &[u8] = b"game";
 pub const PLAYER_SEED: &[u8] = b"player";
+pub const OFFER_SEED: &[u8] = b"offer";
+pub const MARKET_SEED: &[u8] = b"market";
+pub const BUFFER_SEED: &[u8] = b"buffer";
+pub const SESSION_SEED: &[u8] = b"session";
+pub const AGENT_SEED: &[u8] = b"agent";
+pub const ACTION_LOG_SEED: &[u8] = b"action_log";
+pub const GAME_CONFIG_SEED: &[u8] = b"game_config";
+pub const PROGRAM_CONFIG_SEED: &[u8] = b"program_config";
+pub const TREASURY_SEED: &[u8] = b"treasury";
+pub const PROFILE_SEED: &[u8] = b"profile";
+pub const SEASON_SEED: &[u8] = b"season";
+pub const QUEUE_SEED: &[u8] = b"queue";
+pub const GAME_COUNTER_SEED: &[u8] = b"game_counter";
+// Delegation program PDAs
+pub const DELEGATION_RECORD_SEED: &[u8] = b"delegation";
+pub const DELEGATION_METADATA_SEED: &[u8] = b"delegation-metadata";
 
 // Constants
-pub const GRID_SIZE: u8 = 8;
-pub const MAX_PLAYERS: u8 = 4;
-pub const MAX_UNITS: u8 = 20;
-pub const INITIAL_GOLD: u64 = 100;
-pub const INITIAL_WOOD: u64 = 50;
-pub const UNIT_COST_GOLD: u64 = 25;
-pub const DEFENSE_COST_WOOD: u64 = 30;
-pub const RESOURCE_PER_TICK: u64 = 5;
+pub const MARKET_INITIAL_GOLD: u64 = 1000;
+pub const MARKET_INITIAL_WOOD: u64 = 1000;
+pub const MARKET_FEE_BPS: u16 = 30;
+pub const BPS_DENOMINATOR: u64 = 10_000;
+pub const PRICE_SCALE: u64 = 1_000_000;
+pub const TREATY_BREAK_COOLDOWN: i64 = 60;
+pub const TREATY_BREAK_PENALTY: u64 = 100;
+pub const BARBARIAN_WAVE_INTERVAL_SLOTS: u64 = 1500;
+pub const MAX_SESSION_DURATION: i64 = 24 * 60 * 60;
+pub const AUTOPLAY_INTERVAL_SLOTS: u64 = 10;
+/// Records kept by the action log before the oldest are overwritten
+pub const ACTION_LOG_CAPACITY: usize = 500;
+/// `ActionRecord::player` for actions no player took
+pub const NO_PLAYER: u8 = u8::MAX;
+/// Highest protocol fee the admin can set (10% of the pot)
+pub const MAX_PROTOCOL_FEE_BPS: u16 = 1_000;
+pub const INITIAL_RATING: u32 = 1200;
+/// Most rating a player can gain or lose in one game
+pub const RATING_K: f64 = 32.0;
+pub const MIN_RATING: u32 = 100;
+/// Players kept on a season leaderboard
+pub const SEASON_BOARD_SIZE: usize = 10;
+pub const SEASON_WIN_POINTS: u32 = 3;
+pub const SEASON_DRAW_POINTS: u32 = 1;
+/// Share of a season's prize pool paid to each of the top ranks
+pub const SEASON_PRIZE_SHARES_BPS: [u16; 3] = [5_000, 3_000, 2_000];
+/// Players a match queue can hold at once
+pub const QUEUE_CAPACITY: usize = 16;
+/// First ID handed out by the game counter; create_game only accepts IDs below it.
+/// Kept under 2^53 so clients can hold every ID in a JavaScript number.
+pub const FIRST_ASSIGNED_GAME_ID: u64 = 1 << 52;
+
+// Gameplay permissions that can be granted to session keys
+pub const PERMISSION_MOVE: u16 = 1 << 0;
+pub const PERMISSION_BUILD: u16 = 1 << 1;
+pub const PERMISSION_TRAIN: u16 = 1 << 2;
+pub const PERMISSION_COLLECT: u16 = 1 << 3;
+pub const PERMISSION_STRATEGY: u16 = 1 << 4;
+pub const PERMISSION_TRADE: u16 = 1 << 5;
+pub const PERMISSION_DIPLOMACY: u16 = 1 << 6;
+pub const PERMISSION_ALL_GAMEPLAY: u16 = (1 << 7) - 1;
+/// Agents may never stake, claim or trade away resources
+pub const PERMISSION_AGENT_ALLOWED: u16 =
+    PERMISSION_MOVE | PERMISSION_BUILD | PERMISSION_TRAIN | PERMISSION_COLLECT;
 
 #[program]
 pub mod ghostnet {
     use super::*;
 
     /// Create a new game lobby
-    pub fn create_game(ctx: Context<CreateGame>, game_id: u64, stake_amount: u64) -> Result<()> {
-        let game = &mut ctx.accounts.game;
-        let creator = ctx.accounts.creator.key();
-
-        game.game_id = game_id;
-        game.creator = creator;
-        game.stake_amount = stake_amount;
-        game.player_count = 0;
-        game.status = GameStatus::Lobby;
-        game.turn = 0;
-        game.winner = None;
-        game.created_at = Clock::get()?.unix_timestamp;
-        game.started_at = 0;
-        game.finished_at = 0;
-
-        // Init empty grid
-        game.grid = [[TileState::Empty; 8]; 8];
+    pub fn create_game(
+        ctx: Context<CreateGame>,
+        game_id: u64,
+        stake_amount: u64,
+        team_layout: TeamLayout,
+        victory_condition: VictoryCondition,
+        game_mode: GameMode,
+    ) -> Result<()> {
+        require!(game_id < FIRST_ASSIGNED_GAME_ID, GameError::ReservedGameId);
+        let accounts = ctx.accounts;
+        open_game(
+            &mut accounts.game,
+            game_id,
+            ctx.bumps.game,
+            accounts.creator.key(),
+            stake_amount,
+            (team_layout, victory_condition, game_mode),
+            &accounts.program_config,
+            &accounts.game_config,
+        )
+    }
 
-        msg!("Game {} created by {}", game_id, creator);
-        Ok(())
+    /// Create a game under the next sequential ID from the game counter and return that ID.
+    /// The game lives at the usual `[GAME_SEED, id]` PDA, so clients derive it with
+    /// `game_counter.next_id` read beforehand. Pass the creator's profile to also record the ID
+    /// there as `last_created_game_id`.
+    pub fn create_next_game(
+        ctx: Context<CreateNextGame>,
+        stake_amount: u64,
+        team_layout: TeamLayout,
+        victory_condition: VictoryCondition,
+        game_mode: GameMode,
+    ) -> Result<u64> {
+        let accounts = ctx.accounts;
+        let game_id = accounts.game_counter.assign();
+        open_game(
+            &mut accounts.game,
+            game_id,
+            ctx.bumps.game,
+            accounts.creator.key(),
+            stake_amount,
+            (team_layout, victory_condition, game_mode),
+            &accounts.program_config,
+            &accounts.game_config,
+        )?;
+        if let Some(profile) = accounts.creator_profile.as_mut() {
+            profile.last_created_game_id = Some(game_id);
+        }
+        Ok(game_id)
     }
 
     /// Join an existing game
-    pub fn join_game(ctx: Context<JoinGame>, game_id: u64) -> Result<()> {
+    pub fn join_game(ctx: Context<JoinGame>, _game_id: u64) -> Result<()> {
         let game = &mut ctx.accounts.game;
         let player_state = &mut ctx.accounts.player_state;
         let player = ctx.accounts.player.key();
 
-        require!(game.status == GameStatus::Lobby, GameError::GameNotInLobby);
-        require!(game.player_count < MAX_PLAYERS, GameError::GameFull);
-
-        let player_index = game.player_count;
-        game.player_count += 1;
-
-        player_state.game_id = game_id;
-        player_state.player = player;
-        player_state.player_index = player_index;
-        player_state.gold = INITIAL_GOLD;
-        player_state.wood = INITIAL_WOOD;
-        player_state.units = 3;
-        player_state.score = 0;
-        player_state.is_alive = true;
-        player_state.strategy_mode = StrategyMode::Balanced;
-
-        // Assign starting corner (2x2)
-        let (start_x, start_y): (usize, usize) = match player_index {
-            0 => (0, 0),
-            1 => (6, 0),
-            2 => (0, 6),
-            3 => (6, 6),
-            _ => return Err(GameError::GameFull.into()),
-        };
+        require!(!ctx.accounts.program_config.paused, GameError::ProgramPaused);
+        require!(game.queue_id.is_none(), GameError::MatchedGame);
+        game.seat(player_state, player, ctx.bumps.player_state, Clock::get()?.unix_timestamp)?;
 
-        for dx in 0..2usize {
-            for dy in 0..2usize {
-                game.grid[start_y + dy][start_x + dx] = TileState::Owned {
-                    player: player_index,
-                    units: 1,
-                    has_defense: false,
-                    has_mine: false,
-                };
-            }
+        // Escrow the stake in the game account
+        if game.stake_amount > 0 {
+            anchor_lang::system_program::transfer(
+                CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    anchor_lang::system_program::Transfer {
+                        from: ctx.accounts.player.to_account_info(),
+                        to: game.to_account_info(),
+                    },
+                ),
+                game.stake_amount,
+            )?;
         }
-
-        player_state.score = 40; // 4 tiles * 10 pts
-
-        msg!("Player {} joined game {} as P{}", player, game_id, player_index);
         Ok(())
     }
 
@@ -100,22 +174,45 @@ pub mod ghostnet {
 
         require!(game.status == GameStatus::Lobby, GameError::GameNotInLobby);
         require!(game.player_count >= 2, GameError::NotEnoughPlayers);
+        // 2v2 only starts with both teams full
+        require!(
+            game.team_layout != TeamLayout::TwoVsTwo || game.player_count == MAX_PLAYERS,
+            GameError::NotEnoughPlayers
+        );
         require!(game.creator == ctx.accounts.creator.key(), GameError::NotCreator);
 
-        // Place resource mines in center
-        game.grid[3][3] = TileState::Resource { resource_type: ResourceType::Gold, amount: 500 };
-        game.grid[4][4] = TileState::Resource { resource_type: ResourceType::Gold, amount: 500 };
-        game.grid[3][4] = TileState::Resource { resource_type: ResourceType::Wood, amount: 300 };
-        game.grid[4][3] = TileState::Resource { resource_type: ResourceType::Wood, amount: 300 };
+        // Place the center mines and neutral camps
+        let mut state = game.engine_state([], Clock::get()?.unix_timestamp);
+        state.start().map_err(GameError::from)?;
+        game.store(&state, []);
 
-        game.status = GameStatus::Active;
         game.started_at = Clock::get()?.unix_timestamp;
+        game.next_wave_slot = Clock::get()?.slot + BARBARIAN_WAVE_INTERVAL_SLOTS;
 
+        if let GameMode::BattleRoyale { shrink_interval_slots } = game.game_mode {
+            game.next_storm_slot = Clock::get()?.slot + shrink_interval_slots;
+        }
+
+        // Seed the neutral gold/wood market
+        let market = &mut ctx.accounts.market;
+        market.game_id = game.game_id;
+        market.gold_reserve = MARKET_INITIAL_GOLD;
+        market.wood_reserve = MARKET_INITIAL_WOOD;
+        market.fee_bps = MARKET_FEE_BPS;
+        market.last_trade_at = 0;
+        market.update_prices();
+
+        emit!(GameStarted {
+            game_id: game.game_id,
+            player_count: game.player_count,
+            started_at: game.started_at,
+        });
         msg!("Game {} started with {} players!", game.game_id, game.player_count);
         Ok(())
     }
 
-    /// Move units from one tile to adjacent tile
+    /// Move units from one tile to an adjacent tile, or two tiles straight across an ally's tile.
+    /// Moving onto an ally's tile hands the units over to reinforce it.
     pub fn move_units(
         ctx: Context<MoveUnits>,
         _game_id: u64,
@@ -127,438 +224,3985 @@ pub mod ghostnet {
     ) -> Result<()> {
         let game = &mut ctx.accounts.game;
         let player_state = &mut ctx.accounts.player_state;
+        let now = Clock::get()?.unix_timestamp;
+        authorize(
+            player_state,
+            &ctx.accounts.player.key(),
+            ctx.accounts.session_token.as_deref_mut(),
+            ctx.accounts.agent_authority.as_deref_mut(),
+            PERMISSION_MOVE,
+            None,
+        )?;
 
-        require!(game.status == GameStatus::Active, GameError::GameNotActive);
-        require!(player_state.is_alive, GameError::PlayerEliminated);
-        require!(from_x < GRID_SIZE && from_y < GRID_SIZE, GameError::OutOfBounds);
-        require!(to_x < GRID_SIZE && to_y < GRID_SIZE, GameError::OutOfBounds);
-
-        // Must be adjacent
-        let dx = (from_x as i16 - to_x as i16).unsigned_abs() as u8;
-        let dy = (from_y as i16 - to_y as i16).unsigned_abs() as u8;
-        require!(dx <= 1 && dy <= 1 && (dx + dy) > 0, GameError::NotAdjacent);
-
-        let pi = player_state.player_index;
-        let fx = from_x as usize;
-        let fy = from_y as usize;
-        let tx = to_x as usize;
-        let ty = to_y as usize;
-
-        // Verify ownership of source
-        match game.grid[fy][fx] {
-            TileState::Owned { player, units, .. } => {
-                require!(player == pi, GameError::NotYourTile);
-                require!(units >= unit_count && unit_count > 0, GameError::NotEnoughUnits);
-            }
-            _ => return Err(GameError::NotYourTile.into()),
-        }
+        let action = engine::Action::Move {
+            player: player_state.player_index,
+            from: (from_x, from_y),
+            to: (to_x, to_y),
+            count: unit_count,
+        };
+        let record = run_action(game, &mut [&mut **player_state], action, now)?;
+        log_action(game, &ctx.accounts.action_log, record)
+    }
 
-        // Remove units from source
-        if let TileState::Owned { ref mut units, .. } = game.grid[fy][fx] {
-            *units -= unit_count;
-        }
+    /// Build defense on your tile
+    pub fn build_defense(ctx: Context<BuildDefense>, _game_id: u64, x: u8, y: u8) -> Result<()> {
+        let game = &mut ctx.accounts.game;
+        let ps = &mut ctx.accounts.player_state;
+        authorize(
+            ps,
+            &ctx.accounts.player.key(),
+            ctx.accounts.session_token.as_deref_mut(),
+            ctx.accounts.agent_authority.as_deref_mut(),
+            PERMISSION_BUILD,
+            Some((ResourceType::Wood, game.balance.defense_cost_wood)),
+        )?;
 
-        // Handle destination
-        let dest = game.grid[ty][tx];
-        match dest {
-            TileState::Empty => {
-                game.grid[ty][tx] = TileState::Owned {
-                    player: pi,
-                    units: unit_count,
-                    has_defense: false,
-                    has_mine: false,
-                };
-                player_state.score += 10;
-            }
-            TileState::Owned { player: owner, units: def_units, has_defense, has_mine } => {
-                if owner == pi {
-                    // Reinforce
-                    if let TileState::Owned { ref mut units, .. } = game.grid[ty][tx] {
-                        *units += unit_count;
-                    }
-                } else {
-                    // Combat
-                    let atk = unit_count as u16;
-                    let def_bonus: u16 = if has_defense { 2 } else { 0 };
-                    let def = def_units as u16 + def_bonus;
-
-                    if atk > def {
-                        let remaining = ((atk - def) as u8).max(1);
-                        game.grid[ty][tx] = TileState::Owned {
-                            player: pi,
-                            units: remaining,
-                            has_defense: false,
-                            has_mine: has_mine,
-                        };
-                        player_state.score += 50;
-                    } else {
-                        let remaining = ((def - atk) as u8).max(1);
-                        game.grid[ty][tx] = TileState::Owned {
-                            player: owner,
-                            units: remaining,
-                            has_defense,
-                            has_mine,
-                        };
-                    }
-                }
-            }
-            TileState::Resource { resource_type, amount } => {
-                match resource_type {
-                    ResourceType::Gold => player_state.gold += amount,
-                    ResourceType::Wood => player_state.wood += amount,
-                }
-                game.grid[ty][tx] = TileState::Owned {
-                    player: pi,
-                    units: unit_count,
-                    has_defense: false,
-                    has_mine: true,
-                };
-                player_state.score += 100;
-            }
-        }
+        let action = engine::Action::BuildDefense { player: ps.player_index, x, y };
+        let record = run_action(game, &mut [&mut **ps], action, Clock::get()?.unix_timestamp)?;
+        log_action(game, &ctx.accounts.action_log, record)
+    }
 
-        game.turn += 1;
-        msg!("P{} moved {} units ({},{}) -> ({},{})", pi, unit_count, from_x, from_y, to_x, to_y);
-        Ok(())
+    /// Train new units (costs gold)
+    pub fn train_units(ctx: Context<TrainUnits>, _game_id: u64, x: u8, y: u8, count: u8) -> Result<()> {
+        let game = &mut ctx.accounts.game;
+        let ps = &mut ctx.accounts.player_state;
+        authorize(
+            ps,
+            &ctx.accounts.player.key(),
+            ctx.accounts.session_token.as_deref_mut(),
+            ctx.accounts.agent_authority.as_deref_mut(),
+            PERMISSION_TRAIN,
+            Some((ResourceType::Gold, count as u64 * game.balance.unit_cost_gold)),
+        )?;
+
+        let action = engine::Action::TrainUnits { player: ps.player_index, x, y, count };
+        let record = run_action(game, &mut [&mut **ps], action, Clock::get()?.unix_timestamp)?;
+        log_action(game, &ctx.accounts.action_log, record)
     }
 
-    /// Build defense on your tile
-    pub fn build_defense(ctx: Context<BuildDefense>, _game_id: u64, x: u8, y: u8) -> Result<()> {
+    /// Collect resources from owned tiles
+    pub fn collect_resources(ctx: Context<CollectResources>, _game_id: u64) -> Result<()> {
         let game = &mut ctx.accounts.game;
         let ps = &mut ctx.accounts.player_state;
+        authorize(
+            ps,
+            &ctx.accounts.player.key(),
+            ctx.accounts.session_token.as_deref_mut(),
+            ctx.accounts.agent_authority.as_deref_mut(),
+            PERMISSION_COLLECT,
+            None,
+        )?;
 
-        require!(game.status == GameStatus::Active, GameError::GameNotActive);
-        require!(ps.is_alive, GameError::PlayerEliminated);
-        require!(x < GRID_SIZE && y < GRID_SIZE, GameError::OutOfBounds);
-        require!(ps.wood >= DEFENSE_COST_WOOD, GameError::NotEnoughResources);
+        let action = engine::Action::CollectResources { player: ps.player_index };
+        let record = run_action(game, &mut [&mut **ps], action, Clock::get()?.unix_timestamp)?;
+        log_action(game, &ctx.accounts.action_log, record)
+    }
 
-        let xi = x as usize;
-        let yi = y as usize;
+    /// Set strategy mode for agent
+    pub fn set_strategy(ctx: Context<SetStrategy>, _game_id: u64, mode: StrategyMode) -> Result<()> {
+        let ps = &mut ctx.accounts.player_state;
+        authorize(
+            ps,
+            &ctx.accounts.player.key(),
+            ctx.accounts.session_token.as_deref_mut(),
+            None,
+            PERMISSION_STRATEGY,
+            None,
+        )?;
+        ps.strategy_mode = mode.clone();
+        ps.strategy_profile = None;
+        emit!(StrategyChanged {
+            game_id: ps.game_id,
+            player_index: ps.player_index,
+            mode: mode.clone(),
+            profile: ps.profile(),
+            custom: false,
+        });
+        msg!("P{} strategy -> {:?}", ps.player_index, mode);
+        Ok(())
+    }
 
-        match game.grid[yi][xi] {
-            TileState::Owned { player, has_defense, .. } => {
-                require!(player == ps.player_index, GameError::NotYourTile);
-                require!(!has_defense, GameError::AlreadyHasDefense);
-            }
-            _ => return Err(GameError::NotYourTile.into()),
-        }
+    /// Replace the mode preset with custom weights and an attack risk threshold
+    pub fn set_strategy_profile(ctx: Context<SetStrategy>, _game_id: u64, profile: StrategyProfile) -> Result<()> {
+        let ps = &mut ctx.accounts.player_state;
+        authorize(
+            ps,
+            &ctx.accounts.player.key(),
+            ctx.accounts.session_token.as_deref_mut(),
+            None,
+            PERMISSION_STRATEGY,
+            None,
+        )?;
+        require!(
+            engine::StrategyProfile::from(profile).is_valid(),
+            GameError::InvalidStrategyProfile
+        );
+        ps.strategy_profile = Some(profile);
+        emit!(StrategyChanged {
+            game_id: ps.game_id,
+            player_index: ps.player_index,
+            mode: ps.strategy_mode.clone(),
+            profile,
+            custom: true,
+        });
+        msg!("P{} strategy profile -> {:?}", ps.player_index, profile);
+        Ok(())
+    }
 
-        if let TileState::Owned { ref mut has_defense, .. } = game.grid[yi][xi] {
-            *has_defense = true;
-        }
-        ps.wood -= DEFENSE_COST_WOOD;
-        ps.score += 20;
+    /// Opt in or out of on-chain autoplay driven by the strategy mode
+    pub fn set_autoplay(ctx: Context<SetAutoplay>, _game_id: u64, enabled: bool) -> Result<()> {
+        let ps = &mut ctx.accounts.player_state;
+        authorize(
+            ps,
+            &ctx.accounts.player.key(),
+            ctx.accounts.session_token.as_deref_mut(),
+            None,
+            PERMISSION_STRATEGY,
+            None,
+        )?;
+        ps.autoplay = enabled;
+        log_action(
+            &ctx.accounts.game,
+            &ctx.accounts.action_log,
+            ActionRecord {
+                result: enabled as u8,
+                ..ActionRecord::new(ActionKind::Autoplay, ctx.accounts.game.turn, ps.player_index)
+            },
+        )?;
 
-        msg!("P{} built defense at ({},{})", ps.player_index, x, y);
+        msg!("P{} autoplay -> {}", ps.player_index, enabled);
         Ok(())
     }
 
-    /// Train new units (costs gold)
-    pub fn train_units(ctx: Context<TrainUnits>, _game_id: u64, x: u8, y: u8, count: u8) -> Result<()> {
+    /// Play one action for an opted-in player using their strategy's built-in policy (permissionless crank)
+    pub fn autoplay_tick(ctx: Context<AutoplayTick>, _game_id: u64) -> Result<()> {
         let game = &mut ctx.accounts.game;
         let ps = &mut ctx.accounts.player_state;
+        let clock = Clock::get()?;
+
+        require!(ps.autoplay, GameError::AutoplayDisabled);
+        require!(
+            ps.autoplay_ticks == 0 || clock.slot >= ps.last_autoplay_slot + AUTOPLAY_INTERVAL_SLOTS,
+            GameError::AutoplayNotReady
+        );
+
+        let action = game.engine_state([&**ps], clock.unix_timestamp).autoplay_action(
+            ps.player_index,
+            &ps.profile().into(),
+            ps.autoplay_ticks,
+        );
+        ps.last_autoplay_slot = clock.slot;
+        ps.autoplay_ticks += 1;
+
+        msg!("P{} autoplay ({:?}): {:?}", ps.player_index, ps.strategy_mode, action);
+        let record = run_action(game, &mut [&mut **ps], action, clock.unix_timestamp)?;
+        log_action(game, &ctx.accounts.action_log, record)
+    }
+
+    /// Offer gold or wood to other players (escrowed until accepted or cancelled)
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_trade_offer(
+        ctx: Context<CreateTradeOffer>,
+        game_id: u64,
+        offer_id: u64,
+        offer_resource: ResourceType,
+        offer_amount: u64,
+        request_resource: ResourceType,
+        request_amount: u64,
+        target: Option<Pubkey>,
+        expires_at: i64,
+    ) -> Result<()> {
+        let game = &ctx.accounts.game;
+        let ps = &mut ctx.accounts.player_state;
+        let offer = &mut ctx.accounts.offer;
+        let now = Clock::get()?.unix_timestamp;
+        authorize(
+            ps,
+            &ctx.accounts.player.key(),
+            ctx.accounts.session_token.as_deref_mut(),
+            None,
+            PERMISSION_TRADE,
+            Some((offer_resource, offer_amount)),
+        )?;
 
         require!(game.status == GameStatus::Active, GameError::GameNotActive);
         require!(ps.is_alive, GameError::PlayerEliminated);
-        require!(x < GRID_SIZE && y < GRID_SIZE, GameError::OutOfBounds);
+        require!(offer_resource != request_resource, GameError::InvalidTradeOffer);
+        require!(offer_amount > 0 && request_amount > 0, GameError::InvalidTradeOffer);
+        require!(target != Some(ps.player), GameError::CannotTradeWithSelf);
+        require!(expires_at > now, GameError::OfferExpired);
+        require!(*ps.resource(offer_resource) >= offer_amount, GameError::NotEnoughResources);
 
-        let cost = count as u64 * UNIT_COST_GOLD;
-        require!(ps.gold >= cost, GameError::NotEnoughResources);
-        require!(ps.units + count <= MAX_UNITS, GameError::MaxUnitsReached);
+        // Escrow the offered resource in the offer account
+        *ps.resource(offer_resource) -= offer_amount;
 
-        let xi = x as usize;
-        let yi = y as usize;
+        offer.game_id = game_id;
+        offer.offer_id = offer_id;
+        offer.maker = ps.player;
+        offer.target = target;
+        offer.offer_resource = offer_resource;
+        offer.offer_amount = offer_amount;
+        offer.request_resource = request_resource;
+        offer.request_amount = request_amount;
+        offer.created_at = now;
+        offer.expires_at = expires_at;
+        let target_index = target
+            .and_then(|target| game.players[..game.player_count as usize].iter().position(|p| *p == target))
+            .map_or(u8::MAX, |index| index as u8);
+        log_action(
+            game,
+            &ctx.accounts.action_log,
+            ActionRecord {
+                x: target_index,
+                result: offer_resource as u8,
+                to_x: request_resource as u8,
+                value: compact(offer_amount),
+                extra: compact(request_amount),
+                ..ActionRecord::new(ActionKind::OfferTrade, game.turn, ps.player_index)
+            },
+        )?;
 
-        match game.grid[yi][xi] {
-            TileState::Owned { player, .. } => {
-                require!(player == ps.player_index, GameError::NotYourTile);
-            }
-            _ => return Err(GameError::NotYourTile.into()),
+        msg!(
+            "P{} offers {} {:?} for {} {:?} (offer {})",
+            ps.player_index, offer_amount, offer_resource, request_amount, request_resource, offer_id
+        );
+        Ok(())
+    }
+
+    /// Accept a trade offer, swapping resources atomically
+    pub fn accept_trade_offer(ctx: Context<AcceptTradeOffer>, _game_id: u64, _offer_id: u64) -> Result<()> {
+        let game = &ctx.accounts.game;
+        let offer = &ctx.accounts.offer;
+        let maker_state = &mut ctx.accounts.maker_state;
+        let taker_state = &mut ctx.accounts.taker_state;
+        authorize(
+            taker_state,
+            &ctx.accounts.taker.key(),
+            ctx.accounts.session_token.as_deref_mut(),
+            None,
+            PERMISSION_TRADE,
+            Some((offer.request_resource, offer.request_amount)),
+        )?;
+
+        require!(game.status == GameStatus::Active, GameError::GameNotActive);
+        require!(taker_state.is_alive, GameError::PlayerEliminated);
+        require!(taker_state.player != offer.maker, GameError::CannotTradeWithSelf);
+        if let Some(target) = offer.target {
+            require!(target == taker_state.player, GameError::OfferNotForYou);
         }
+        require!(Clock::get()?.unix_timestamp < offer.expires_at, GameError::OfferExpired);
+        require!(
+            *taker_state.resource(offer.request_resource) >= offer.request_amount,
+            GameError::NotEnoughResources
+        );
+
+        *taker_state.resource(offer.request_resource) -= offer.request_amount;
+        *maker_state.resource(offer.request_resource) += offer.request_amount;
+        *taker_state.resource(offer.offer_resource) += offer.offer_amount;
+
+        log_action(
+            game,
+            &ctx.accounts.action_log,
+            ActionRecord {
+                x: maker_state.player_index,
+                result: offer.offer_resource as u8,
+                value: compact(offer.offer_amount),
+                extra: compact(offer.request_amount),
+                ..ActionRecord::new(ActionKind::Trade, game.turn, taker_state.player_index)
+            },
+        )?;
 
-        if let TileState::Owned { ref mut units, .. } = game.grid[yi][xi] {
-            *units += count;
+        msg!(
+            "P{} accepted offer {} from P{}",
+            taker_state.player_index, offer.offer_id, maker_state.player_index
+        );
+        Ok(())
+    }
+
+    /// Cancel a trade offer (maker or their session any time, anyone once expired)
+    pub fn cancel_trade_offer(ctx: Context<CancelTradeOffer>, _game_id: u64, _offer_id: u64) -> Result<()> {
+        let offer = &ctx.accounts.offer;
+        let maker_state = &mut ctx.accounts.maker_state;
+
+        let expired = Clock::get()?.unix_timestamp >= offer.expires_at;
+        if !expired {
+            authorize(
+                maker_state,
+                &ctx.accounts.caller.key(),
+                ctx.accounts.session_token.as_deref_mut(),
+                None,
+                PERMISSION_TRADE,
+                None,
+            )?;
         }
 
-        ps.gold -= cost;
-        ps.units += count;
+        // Return escrowed resource to the maker
+        *maker_state.resource(offer.offer_resource) += offer.offer_amount;
+        log_action(
+            &ctx.accounts.game,
+            &ctx.accounts.action_log,
+            ActionRecord {
+                result: offer.offer_resource as u8,
+                value: compact(offer.offer_amount),
+                ..ActionRecord::new(ActionKind::CancelOffer, ctx.accounts.game.turn, maker_state.player_index)
+            },
+        )?;
 
-        msg!("P{} trained {} units at ({},{})", ps.player_index, count, x, y);
+        msg!("Offer {} from P{} cancelled", offer.offer_id, maker_state.player_index);
         Ok(())
     }
 
-    /// Collect resources from owned tiles
-    pub fn collect_resources(ctx: Context<CollectResources>, _game_id: u64) -> Result<()> {
+    /// Swap gold for wood (or back) against the game's constant-product market
+    pub fn swap(
+        ctx: Context<Swap>,
+        _game_id: u64,
+        resource_in: ResourceType,
+        amount_in: u64,
+        min_amount_out: u64,
+    ) -> Result<()> {
         let game = &ctx.accounts.game;
+        let market = &mut ctx.accounts.market;
         let ps = &mut ctx.accounts.player_state;
+        authorize(
+            ps,
+            &ctx.accounts.player.key(),
+            ctx.accounts.session_token.as_deref_mut(),
+            None,
+            PERMISSION_TRADE,
+            Some((resource_in, amount_in)),
+        )?;
 
         require!(game.status == GameStatus::Active, GameError::GameNotActive);
         require!(ps.is_alive, GameError::PlayerEliminated);
+        require!(amount_in > 0, GameError::InvalidSwap);
+        require!(*ps.resource(resource_in) >= amount_in, GameError::NotEnoughResources);
 
-        let mut gold_gain: u64 = 0;
-        let mut wood_gain: u64 = 0;
-
-        for row in &game.grid {
-            for tile in row {
-                if let TileState::Owned { player, has_mine, .. } = tile {
-                    if *player == ps.player_index {
-                        gold_gain += RESOURCE_PER_TICK;
-                        wood_gain += RESOURCE_PER_TICK;
-                        if *has_mine {
-                            gold_gain += RESOURCE_PER_TICK * 2;
-                        }
-                    }
-                }
-            }
-        }
+        let resource_out = resource_in.other();
+        let amount_out = market.quote(resource_in, amount_in);
+        require!(amount_out > 0, GameError::InvalidSwap);
+        require!(amount_out >= min_amount_out, GameError::SlippageExceeded);
+
+        *ps.resource(resource_in) -= amount_in;
+        *ps.resource(resource_out) += amount_out;
+        *market.reserve(resource_in) += amount_in;
+        *market.reserve(resource_out) -= amount_out;
+        market.last_trade_at = Clock::get()?.unix_timestamp;
+        market.update_prices();
 
-        ps.gold += gold_gain;
-        ps.wood += wood_gain;
+        log_action(
+            game,
+            &ctx.accounts.action_log,
+            ActionRecord {
+                result: resource_in as u8,
+                value: compact(amount_in),
+                extra: compact(amount_out),
+                ..ActionRecord::new(ActionKind::Swap, game.turn, ps.player_index)
+            },
+        )?;
 
-        msg!("P{} collected {} gold, {} wood", ps.player_index, gold_gain, wood_gain);
+        msg!(
+            "P{} swapped {} {:?} for {} {:?}",
+            ps.player_index, amount_in, resource_in, amount_out, resource_out
+        );
         Ok(())
     }
 
-    /// Set strategy mode for agent
-    pub fn set_strategy(ctx: Context<SetStrategy>, _game_id: u64, mode: StrategyMode) -> Result<()> {
-        let ps = &mut ctx.accounts.player_state;
-        ps.strategy_mode = mode.clone();
-        msg!("P{} strategy -> {:?}", ps.player_index, mode);
+    /// Propose peace or an alliance to another player
+    pub fn propose_treaty(
+        ctx: Context<ProposeTreaty>,
+        _game_id: u64,
+        target_index: u8,
+        treaty: DiplomacyState,
+    ) -> Result<()> {
+        let game = &mut ctx.accounts.game;
+        let ps = &ctx.accounts.player_state;
+        let pi = ps.player_index;
+        authorize(
+            ps,
+            &ctx.accounts.player.key(),
+            ctx.accounts.session_token.as_deref_mut(),
+            None,
+            PERMISSION_DIPLOMACY,
+            None,
+        )?;
+
+        require!(game.status == GameStatus::Active, GameError::GameNotActive);
+        require!(ps.is_alive, GameError::PlayerEliminated);
+        require!(target_index < game.player_count && target_index != pi, GameError::InvalidTreaty);
+        require!(!game.are_teammates(pi, target_index), GameError::InvalidTreaty);
+        require!(treaty != DiplomacyState::War, GameError::InvalidTreaty);
+        require!(game.diplomacy_between(pi, target_index) != treaty, GameError::InvalidTreaty);
+
+        game.treaty_proposals[pi as usize][target_index as usize] = Some(treaty);
+        log_action(
+            game,
+            &ctx.accounts.action_log,
+            ActionRecord {
+                x: target_index,
+                result: treaty as u8,
+                ..ActionRecord::new(ActionKind::ProposeTreaty, game.turn, pi)
+            },
+        )?;
+
+        msg!("P{} proposed {:?} to P{}", pi, treaty, target_index);
         Ok(())
     }
 
-    /// End game - declare winner
-    pub fn end_game(ctx: Context<EndGame>) -> Result<()> {
+    /// Accept a pending treaty proposal
+    pub fn accept_treaty(ctx: Context<AcceptTreaty>, _game_id: u64, proposer_index: u8) -> Result<()> {
         let game = &mut ctx.accounts.game;
+        let ps = &ctx.accounts.player_state;
+        let pi = ps.player_index;
+        authorize(
+            ps,
+            &ctx.accounts.player.key(),
+            ctx.accounts.session_token.as_deref_mut(),
+            None,
+            PERMISSION_DIPLOMACY,
+            None,
+        )?;
+
         require!(game.status == GameStatus::Active, GameError::GameNotActive);
-        game.status = GameStatus::Finished;
-        game.finished_at = Clock::get()?.unix_timestamp;
-        msg!("Game {} finished!", game.game_id);
+        require!(ps.is_alive, GameError::PlayerEliminated);
+        require!(proposer_index < game.player_count, GameError::InvalidTreaty);
+
+        let treaty = game.treaty_proposals[proposer_index as usize][pi as usize]
+            .ok_or(GameError::NoTreatyProposal)?;
+
+        game.set_diplomacy(pi, proposer_index, treaty);
+        log_action(
+            game,
+            &ctx.accounts.action_log,
+            ActionRecord {
+                x: proposer_index,
+                result: treaty as u8,
+                ..ActionRecord::new(ActionKind::Treaty, game.turn, pi)
+            },
+        )?;
+
+        msg!("P{} accepted {:?} with P{}", pi, treaty, proposer_index);
         Ok(())
     }
 
-    /// Delegate game to MagicBlock ER for real-time execution
-    pub fn delegate_game(ctx: Context<DelegateGame>) -> Result<()> {
-        let game = &ctx.accounts.game;
-        let game_info = ctx.accounts.game.to_account_info();
-        let payer_info = ctx.accounts.payer.to_account_info();
-        let system_info = ctx.accounts.system_program.to_account_info();
-        let delegation_program_info = ctx.accounts.delegation_program.to_account_info();
-        let buffer_info = ctx.accounts.buffer.to_account_info();
-        let record_info = ctx.accounts.delegation_record.to_account_info();
-        let metadata_info = ctx.accounts.delegation_metadata.to_account_info();
-        let owner_program_info = ctx.accounts.owner_program.to_account_info();
-
-        // Build seeds for PDA signing
-        let game_id_bytes = game.game_id.to_le_bytes();
-        let (_, bump) = Pubkey::find_program_address(
-            &[GAME_SEED, &game_id_bytes],
-            &crate::ID,
-        );
-        let signer_seeds: &[&[u8]] = &[GAME_SEED, &game_id_bytes, &[bump]];
+    /// Break a treaty, returning to war after a cooldown (score penalty)
+    pub fn break_treaty(ctx: Context<BreakTreaty>, _game_id: u64, other_index: u8) -> Result<()> {
+        let game = &mut ctx.accounts.game;
+        let ps = &mut ctx.accounts.player_state;
+        let pi = ps.player_index;
+        let now = Clock::get()?.unix_timestamp;
+        authorize(
+            ps,
+            &ctx.accounts.player.key(),
+            ctx.accounts.session_token.as_deref_mut(),
+            None,
+            PERMISSION_DIPLOMACY,
+            None,
+        )?;
 
-        // CPI to delegation program
-        let ix = anchor_lang::solana_program::instruction::Instruction {
-            program_id: DELEGATION_PROGRAM_ID,
-            accounts: vec![
-                anchor_lang::solana_program::instruction::AccountMeta::new(payer_info.key(), true),
-                anchor_lang::solana_program::instruction::AccountMeta::new(game_info.key(), false),
-                anchor_lang::solana_program::instruction::AccountMeta::new_readonly(owner_program_info.key(), false),
-                anchor_lang::solana_program::instruction::AccountMeta::new(buffer_info.key(), false),
-                anchor_lang::solana_program::instruction::AccountMeta::new(record_info.key(), false),
-                anchor_lang::solana_program::instruction::AccountMeta::new(metadata_info.key(), false),
-                anchor_lang::solana_program::instruction::AccountMeta::new_readonly(system_info.key(), false),
-            ],
-            data: vec![0], // delegate instruction discriminator
-        };
+        require!(game.status == GameStatus::Active, GameError::GameNotActive);
+        require!(other_index < game.player_count, GameError::InvalidTreaty);
+        require!(!game.are_teammates(pi, other_index), GameError::InvalidTreaty);
+        require!(
+            game.diplomacy_between(pi, other_index) != DiplomacyState::War,
+            GameError::InvalidTreaty
+        );
 
-        anchor_lang::solana_program::program::invoke_signed(
-            &ix,
-            &[
-                payer_info,
-                game_info,
-                owner_program_info,
-                buffer_info,
-                record_info,
-                metadata_info,
-                system_info,
-                delegation_program_info,
-            ],
-            &[signer_seeds],
+        game.set_diplomacy(pi, other_index, DiplomacyState::War);
+        game.truce_until[pi as usize][other_index as usize] = now + TREATY_BREAK_COOLDOWN;
+        game.truce_until[other_index as usize][pi as usize] = now + TREATY_BREAK_COOLDOWN;
+        ps.score = ps.score.saturating_sub(TREATY_BREAK_PENALTY);
+        log_action(
+            game,
+            &ctx.accounts.action_log,
+            ActionRecord {
+                x: other_index,
+                result: DiplomacyState::War as u8,
+                ..ActionRecord::new(ActionKind::BreakTreaty, game.turn, pi)
+            },
         )?;
 
-        msg!("Game {} delegated to ER!", game.game_id);
+        msg!("P{} broke treaty with P{}", pi, other_index);
         Ok(())
     }
-}
-
-// ==================== ACCOUNTS ====================
 
-#[derive(Accounts)]
-#[instruction(game_id: u64)]
-pub struct CreateGame<'info> {
-    #[account(
-        init,
-        payer = creator,
-        space = 8 + Game::LEN,
-        seeds = [GAME_SEED, &game_id.to_le_bytes()],
-        bump
-    )]
-    pub game: Account<'info, Game>,
-    #[account(mut)]
-    pub creator: Signer<'info>,
-    pub system_program: Program<'info, System>,
-}
+    /// Authorize an ephemeral session key to play on the player's behalf
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_session(
+        ctx: Context<CreateSession>,
+        game_id: u64,
+        session_key: Pubkey,
+        valid_until: i64,
+        permissions: u16,
+        max_gold_spend: u64,
+        max_wood_spend: u64,
+        top_up_lamports: u64,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
 
-#[derive(Accounts)]
-#[instruction(game_id: u64)]
-pub struct JoinGame<'info> {
-    #[account(
-        mut,
-        seeds = [GAME_SEED, &game_id.to_le_bytes()],
-        bump
-    )]
-    pub game: Account<'info, Game>,
-    #[account(
-        init,
-        payer = player,
-        space = 8 + PlayerState::LEN,
-        seeds = [PLAYER_SEED, &game_id.to_le_bytes(), player.key().as_ref()],
-        bump
-    )]
-    pub player_state: Account<'info, PlayerState>,
-    #[account(mut)]
-    pub player: Signer<'info>,
-    pub system_program: Program<'info, System>,
-}
+        require!(
+            valid_until > now && valid_until <= now + MAX_SESSION_DURATION,
+            GameError::InvalidSession
+        );
+        require!(
+            permissions != 0 && permissions & !PERMISSION_ALL_GAMEPLAY == 0,
+            GameError::InvalidSession
+        );
+        require!(session_key != ctx.accounts.player.key(), GameError::InvalidSession);
 
-#[derive(Accounts)]
-pub struct StartGame<'info> {
-    #[account(mut)]
-    pub game: Account<'info, Game>,
-    pub creator: Signer<'info>,
-}
+        let session = &mut ctx.accounts.session_token;
+        session.game_id = game_id;
+        session.authority = ctx.accounts.player.key();
+        session.session_key = session_key;
+        session.valid_until = valid_until;
+        session.permissions = permissions;
+        session.max_gold_spend = max_gold_spend;
+        session.max_wood_spend = max_wood_spend;
+        session.gold_spent = 0;
+        session.wood_spent = 0;
+        session.bump = ctx.bumps.session_token;
 
-#[derive(Accounts)]
-#[instruction(game_id: u64)]
-pub struct MoveUnits<'info> {
-    #[account(mut, seeds = [GAME_SEED, &game_id.to_le_bytes()], bump)]
-    pub game: Account<'info, Game>,
-    #[account(mut, seeds = [PLAYER_SEED, &game_id.to_le_bytes(), player.key().as_ref()], bump)]
-    pub player_state: Account<'info, PlayerState>,
-    #[account(mut)]
-    pub player: Signer<'info>,
-}
+        // Fund the session key so it can pay its own transaction fees
+        if top_up_lamports > 0 {
+            anchor_lang::system_program::transfer(
+                CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    anchor_lang::system_program::Transfer {
+                        from: ctx.accounts.player.to_account_info(),
+                        to: ctx.accounts.session_signer.to_account_info(),
+                    },
+                ),
+                top_up_lamports,
+            )?;
+        }
 
-#[derive(Accounts)]
-#[instruction(game_id: u64)]
-pub struct BuildDefense<'info> {
-    #[account(mut, seeds = [GAME_SEED, &game_id.to_le_bytes()], bump)]
-    pub game: Account<'info, Game>,
-    #[account(mut, seeds = [PLAYER_SEED, &game_id.to_le_bytes(), player.key().as_ref()], bump)]
-    pub player_state: Account<'info, PlayerState>,
-    #[account(mut)]
-    pub player: Signer<'info>,
-}
+        msg!("P{} opened session {} until {}", ctx.accounts.player_state.player_index, session_key, valid_until);
+        Ok(())
+    }
 
-#[derive(Accounts)]
-#[instruction(game_id: u64)]
-pub struct TrainUnits<'info> {
-    #[account(mut, seeds = [GAME_SEED, &game_id.to_le_bytes()], bump)]
-    pub game: Account<'info, Game>,
-    #[account(mut, seeds = [PLAYER_SEED, &game_id.to_le_bytes(), player.key().as_ref()], bump)]
-    pub player_state: Account<'info, PlayerState>,
-    #[account(mut)]
-    pub player: Signer<'info>,
-}
+    /// Revoke a session key immediately. Only clears its permissions, so it also works in the ER
+    /// while the token is delegated; `close_session` reclaims the rent afterwards.
+    pub fn revoke_session(ctx: Context<RevokeSession>, _game_id: u64, session_key: Pubkey) -> Result<()> {
+        ctx.accounts.session_token.permissions = 0;
+        msg!("Session {} revoked by {}", session_key, ctx.accounts.player.key());
+        Ok(())
+    }
 
-#[derive(Accounts)]
+    /// Close a revoked or expired session token and return its rent (base layer, once undelegated)
+    pub fn close_session(ctx: Context<CloseSession>, _game_id: u64, session_key: Pubkey) -> Result<()> {
+        let session = &ctx.accounts.session_token;
+        require!(
+            session.permissions == 0 || Clock::get()?.unix_timestamp >= session.valid_until,
+            GameError::SessionStillActive
+        );
+        msg!("Session {} closed", session_key);
+        Ok(())
+    }
+
+    /// Register an AI agent allowed to move, train, build and collect for the player
+    pub fn register_agent(
+        ctx: Context<RegisterAgent>,
+        game_id: u64,
+        agent: Pubkey,
+        permissions: u16,
+        max_actions_per_turn: u16,
+        turn_slots: u64,
+    ) -> Result<()> {
+        require!(
+            permissions != 0 && permissions & !PERMISSION_AGENT_ALLOWED == 0,
+            GameError::InvalidAgent
+        );
+        require!(agent != ctx.accounts.player.key(), GameError::InvalidAgent);
+        require!(max_actions_per_turn == 0 || turn_slots > 0, GameError::InvalidAgent);
+
+        let authority = &mut ctx.accounts.agent_authority;
+        authority.game_id = game_id;
+        authority.player = ctx.accounts.player.key();
+        authority.agent = agent;
+        authority.permissions = permissions;
+        authority.max_actions_per_turn = max_actions_per_turn;
+        authority.turn_slots = turn_slots;
+        authority.current_turn = 0;
+        authority.actions_this_turn = 0;
+        authority.bump = ctx.bumps.agent_authority;
+
+        msg!("P{} registered agent {}", ctx.accounts.player_state.player_index, agent);
+        Ok(())
+    }
+
+    /// Revoke the player's agent, effective immediately. Only clears its permissions, so it also
+    /// works in the ER while the authority is delegated; `close_agent` reclaims the rent afterwards.
+    pub fn revoke_agent(ctx: Context<RevokeAgent>, _game_id: u64) -> Result<()> {
+        let authority = &mut ctx.accounts.agent_authority;
+        authority.permissions = 0;
+        msg!("Agent {} revoked by {}", authority.agent, ctx.accounts.player.key());
+        Ok(())
+    }
+
+    /// Close a revoked agent authority and return its rent (base layer, once undelegated)
+    pub fn close_agent(ctx: Context<CloseAgent>, _game_id: u64) -> Result<()> {
+        let authority = &ctx.accounts.agent_authority;
+        require!(authority.permissions == 0, GameError::AgentStillActive);
+        msg!("Agent {} closed", authority.agent);
+        Ok(())
+    }
+
+    /// End an unstaked game early - declare winner.
+    /// Staked games only finish through check_victory or force_refund.
+    pub fn end_game(ctx: Context<EndGame>) -> Result<()> {
+        let game = &mut ctx.accounts.game;
+        require!(game.status == GameStatus::Active, GameError::GameNotActive);
+        require!(game.creator == ctx.accounts.authority.key(), GameError::NotCreator);
+        require!(game.stake_amount == 0, GameError::StakedGame);
+
+        // Team holding the most territory wins, ties are a draw
+        let now = Clock::get()?.unix_timestamp;
+        let winning_team = game.engine_state([], now).territory_leader();
+        game.finish(winning_team, now);
+
+        msg!("Game {} finished! Winning team: {:?}", game.game_id, winning_team);
+        Ok(())
+    }
+
+    /// Eliminate landless players, then evaluate the game's victory condition and finalize it
+    /// if met (permissionless).
+    /// Every player's PlayerState must be passed as a writable remaining account.
+    pub fn check_victory<'info>(
+        ctx: Context<'_, '_, 'info, 'info, CheckVictory<'info>>,
+        _game_id: u64,
+    ) -> Result<()> {
+        let game = &mut ctx.accounts.game;
+        let now = Clock::get()?.unix_timestamp;
+
+        require!(game.status == GameStatus::Active, GameError::GameNotActive);
+
+        let mut players = load_player_states(game, ctx.remaining_accounts)?;
+        let mut state = game.engine_state(players.iter().map(|ps| &**ps), now);
+        let eliminated = state.eliminate_landless();
+        let outcome = state.victory();
+        game.store(&state, players.iter_mut().map(|ps| &mut **ps));
+        report_eliminations(game, eliminated);
+
+        for ps in players.iter() {
+            ps.exit(&crate::ID)?;
+        }
+
+        // Succeed without a winner so the eliminations above are kept
+        let Some(winning_team) = outcome else {
+            msg!("Game {} continues", game.game_id);
+            return Ok(());
+        };
+        game.finish(winning_team, now);
+
+        msg!("Game {} won by team {:?}", game.game_id, winning_team);
+        Ok(())
+    }
+
+    /// Battle royale: collapse the outermost ring once the slot threshold passes (permissionless).
+    /// Every player's PlayerState must be passed as a writable remaining account.
+    pub fn advance_storm<'info>(
+        ctx: Context<'_, '_, 'info, 'info, AdvanceStorm<'info>>,
+        _game_id: u64,
+    ) -> Result<()> {
+        let game = &mut ctx.accounts.game;
+        let clock = Clock::get()?;
+
+        require!(game.status == GameStatus::Active, GameError::GameNotActive);
+        let GameMode::BattleRoyale { shrink_interval_slots } = game.game_mode else {
+            return Err(GameError::WrongGameMode.into());
+        };
+        require!(clock.slot >= game.next_storm_slot, GameError::StormNotReady);
+
+        let mut players = load_player_states(game, ctx.remaining_accounts)?;
+        let mut refs: Vec<&mut PlayerState> = players.iter_mut().map(|ps| &mut **ps).collect();
+        let record = run_action(game, &mut refs, engine::Action::AdvanceStorm, clock.unix_timestamp)?;
+        game.next_storm_slot = clock.slot + shrink_interval_slots;
+
+        for ps in players.iter() {
+            ps.exit(&crate::ID)?;
+        }
+        log_action(game, &ctx.accounts.action_log, record)
+    }
+
+    /// Send a barbarian wave at the player holding the most territory (permissionless crank)
+    pub fn barbarian_wave(ctx: Context<BarbarianWave>, _game_id: u64) -> Result<()> {
+        let game = &mut ctx.accounts.game;
+        let clock = Clock::get()?;
+
+        require!(game.status == GameStatus::Active, GameError::GameNotActive);
+        require!(clock.slot >= game.next_wave_slot, GameError::WaveNotReady);
+
+        let record = run_action(game, &mut [], engine::Action::BarbarianWave, clock.unix_timestamp)?;
+        game.next_wave_slot = clock.slot + BARBARIAN_WAVE_INTERVAL_SLOTS;
+        log_action(game, &ctx.accounts.action_log, record)
+    }
+
+    /// Claim a share of the pot (winning team splits it, draws refund stakes)
+    pub fn claim_winnings(ctx: Context<ClaimWinnings>, _game_id: u64) -> Result<()> {
+        let game = &mut ctx.accounts.game;
+        let ps = &mut ctx.accounts.player_state;
+
+        require!(game.status == GameStatus::Finished, GameError::GameNotFinished);
+        require!(!ps.has_claimed, GameError::AlreadyClaimed);
+
+        let payout = game.payout_for(ps.team);
+        require!(payout > 0, GameError::NothingToClaim);
+
+        // The first claim sweeps the protocol fee into the treasury
+        if !game.fee_collected && game.fee > 0 {
+            let treasury = ctx.accounts.treasury.as_mut().ok_or(GameError::TreasuryRequired)?;
+            game.fee_collected = true;
+            **game.to_account_info().try_borrow_mut_lamports()? -= game.fee;
+            **treasury.to_account_info().try_borrow_mut_lamports()? += game.fee;
+            treasury.total_collected += game.fee;
+            msg!("Game {} paid {} lamports protocol fee", game.game_id, game.fee);
+        }
+
+        ps.has_claimed = true;
+        **game.to_account_info().try_borrow_mut_lamports()? -= payout;
+        **ctx.accounts.player.to_account_info().try_borrow_mut_lamports()? += payout;
+
+        msg!("P{} claimed {} lamports", ps.player_index, payout);
+        Ok(())
+    }
+
+    /// Open matchmaking queue `queue_id` for one mode and stake tier (admin only).
+    /// Games formed from it snapshot the optional balance preset.
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_match_queue(
+        ctx: Context<CreateMatchQueue>,
+        queue_id: u32,
+        stake_amount: u64,
+        team_layout: TeamLayout,
+        victory_condition: VictoryCondition,
+        game_mode: GameMode,
+        players_per_match: u8,
+        rating_band: u32,
+    ) -> Result<()> {
+        validate_rules(victory_condition, game_mode, Clock::get()?.unix_timestamp)?;
+        require!(
+            (2..=MAX_PLAYERS).contains(&players_per_match)
+                && (team_layout != TeamLayout::TwoVsTwo || players_per_match == MAX_PLAYERS),
+            GameError::InvalidQueue
+        );
+        let (config_version, balance) = balance_from(&ctx.accounts.game_config)?;
+
+        let queue = &mut ctx.accounts.match_queue;
+        queue.queue_id = queue_id;
+        queue.stake_amount = stake_amount;
+        queue.team_layout = team_layout;
+        queue.victory_condition = victory_condition;
+        queue.game_mode = game_mode;
+        queue.players_per_match = players_per_match;
+        queue.rating_band = rating_band;
+        queue.config_version = config_version;
+        queue.balance = balance;
+        queue.count = 0;
+        queue.entries = [QueueEntry::default(); QUEUE_CAPACITY];
+        queue.bump = ctx.bumps.match_queue;
+
+        msg!(
+            "Queue {} opened: {}p {:?} {:?}, stake {}, band {}",
+            queue_id,
+            players_per_match,
+            team_layout,
+            game_mode,
+            stake_amount,
+            rating_band
+        );
+        Ok(())
+    }
+
+    /// Join a queue at your profile's rating, escrowing the stake in the queue
+    pub fn enqueue(ctx: Context<Enqueue>, queue_id: u32) -> Result<()> {
+        require!(!ctx.accounts.program_config.paused, GameError::ProgramPaused);
+        let queue = &mut ctx.accounts.match_queue;
+        let player = ctx.accounts.player.key();
+        require!(queue.position(&player).is_none(), GameError::AlreadyQueued);
+        require!((queue.count as usize) < QUEUE_CAPACITY, GameError::QueueFull);
+
+        let entry = QueueEntry {
+            player,
+            rating: ctx.accounts.player_profile.rating,
+            enqueued_at: Clock::get()?.unix_timestamp,
+        };
+        let slot = queue.count as usize;
+        queue.entries[slot] = entry;
+        queue.count += 1;
+
+        if queue.stake_amount > 0 {
+            anchor_lang::system_program::transfer(
+                CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    anchor_lang::system_program::Transfer {
+                        from: ctx.accounts.player.to_account_info(),
+                        to: queue.to_account_info(),
+                    },
+                ),
+                queue.stake_amount,
+            )?;
+        }
+
+        msg!("{} queued in {} at rating {}", player, queue_id, entry.rating);
+        Ok(())
+    }
+
+    /// Leave a queue and get the escrowed stake back
+    pub fn dequeue(ctx: Context<Dequeue>, queue_id: u32) -> Result<()> {
+        let queue = &mut ctx.accounts.match_queue;
+        let player = ctx.accounts.player.key();
+        let index = queue.position(&player).ok_or(GameError::NotQueued)?;
+        queue.remove(index);
+
+        let stake = queue.stake_amount;
+        **queue.to_account_info().try_borrow_mut_lamports()? -= stake;
+        **ctx.accounts.player.to_account_info().try_borrow_mut_lamports()? += stake;
+
+        msg!("{} left queue {} ({} refunded)", player, queue_id, stake);
+        Ok(())
+    }
+
+    /// Pop `players` from a queue into a new game under the next counter ID and seat them all
+    /// (permissionless).
+    /// The players must all be queued and within `rating_band` of each other; the first one
+    /// becomes the game's creator. Remaining accounts: each player's PlayerState PDA for the
+    /// new game, uninitialized and writable, in `players` order.
+    pub fn form_match<'info>(
+        ctx: Context<'_, '_, 'info, 'info, FormMatch<'info>>,
+        queue_id: u32,
+        players: Vec<Pubkey>,
+    ) -> Result<u64> {
+        require!(!ctx.accounts.program_config.paused, GameError::ProgramPaused);
+        let queue = &mut ctx.accounts.match_queue;
+        require!(players.len() == queue.players_per_match as usize, GameError::InvalidMatch);
+        require!(ctx.remaining_accounts.len() == players.len(), GameError::InvalidPlayerAccounts);
+
+        let mut ratings = Vec::with_capacity(players.len());
+        for (i, player) in players.iter().enumerate() {
+            require!(!players[..i].contains(player), GameError::InvalidMatch);
+            let index = queue.position(player).ok_or(GameError::NotQueued)?;
+            ratings.push(queue.entries[index].rating);
+        }
+        let spread = ratings.iter().max().unwrap_or(&0) - ratings.iter().min().unwrap_or(&0);
+        require!(spread <= queue.rating_band, GameError::InvalidMatch);
+
+        let game_id = ctx.accounts.game_counter.assign();
+        let now = Clock::get()?.unix_timestamp;
+        // A Timed queue stops forming games once its deadline has passed
+        validate_rules(queue.victory_condition, queue.game_mode, now)?;
+        let game = &mut ctx.accounts.game;
+        game.init(game_id, ctx.bumps.game, players[0], queue.stake_amount, now);
+        game.team_layout = queue.team_layout;
+        game.victory_condition = queue.victory_condition;
+        game.game_mode = queue.game_mode;
+        game.fee_bps = ctx.accounts.program_config.fee_bps;
+        game.config_version = queue.config_version;
+        game.balance = queue.balance;
+        game.queue_id = Some(queue_id);
+        emit!(GameCreated {
+            game_id,
+            creator: players[0],
+            stake_amount: queue.stake_amount,
+            team_layout: queue.team_layout,
+            victory_condition: queue.victory_condition,
+            game_mode: queue.game_mode,
+            config_version: queue.config_version,
+            created_at: now,
+        });
+
+        for (player, info) in players.iter().zip(ctx.remaining_accounts) {
+            let seeds: [&[u8]; 3] = [PLAYER_SEED, &game_id.to_le_bytes(), player.as_ref()];
+            let (address, bump) = Pubkey::find_program_address(&seeds, &crate::ID);
+            require_keys_eq!(info.key(), address, GameError::InvalidPlayerAccounts);
+
+            let bump_seed = [bump];
+            let signer_seeds: [&[u8]; 4] = [seeds[0], seeds[1], seeds[2], &bump_seed];
+            create_pda_account(
+                &ctx.accounts.system_program.to_account_info(),
+                &ctx.accounts.payer.to_account_info(),
+                info,
+                8 + PlayerState::LEN,
+                &signer_seeds,
+            )?;
+            info.try_borrow_mut_data()?[..8].copy_from_slice(PlayerState::DISCRIMINATOR);
+
+            let mut player_state = Account::<PlayerState>::try_from(info)?;
+            game.seat(&mut player_state, *player, bump, now)?;
+            player_state.exit(&crate::ID)?;
+
+            // Move the escrowed stake from the queue to the game
+            let index = queue.position(player).ok_or(GameError::NotQueued)?;
+            queue.remove(index);
+            **queue.to_account_info().try_borrow_mut_lamports()? -= queue.stake_amount;
+            **game.to_account_info().try_borrow_mut_lamports()? += queue.stake_amount;
+        }
+
+        emit!(MatchFormed { queue_id, game_id, players: players.clone() });
+        msg!("Queue {} formed game {} with {} players", queue_id, game_id, players.len());
+        Ok(game_id)
+    }
+
+    /// Create the cross-game profile for `player`. Anyone can pay for it, so a game can be
+    /// rated even if a player never set one up.
+    pub fn create_profile(ctx: Context<CreateProfile>, player: Pubkey) -> Result<()> {
+        let profile = &mut ctx.accounts.player_profile;
+        profile.player = player;
+        profile.rating = INITIAL_RATING;
+        profile.games_played = 0;
+        profile.wins = 0;
+        profile.losses = 0;
+        profile.draws = 0;
+        profile.total_score = 0;
+        profile.last_game_id = None;
+        profile.last_created_game_id = None;
+        profile.season_id = 0;
+        profile.season_points = 0;
+        profile.season_wins = 0;
+        profile.bump = ctx.bumps.player_profile;
+
+        msg!("Profile created for {}", player);
+        Ok(())
+    }
+
+    /// Apply a finished game to every player's profile (permissionless, once per game).
+    /// Remaining accounts: every player's PlayerState, then their PlayerProfile in the same order,
+    /// all writable.
+    pub fn settle_ratings<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SettleRatings<'info>>,
+        game_id: u64,
+    ) -> Result<()> {
+        let game = &mut ctx.accounts.game;
+        require!(game.status == GameStatus::Finished, GameError::GameNotFinished);
+        require!(!game.refunded, GameError::GameWasRefunded);
+        require!(!game.ratings_settled, GameError::RatingsAlreadySettled);
+
+        // A game that finished inside the current season's window must count toward it
+        let config = &ctx.accounts.program_config;
+        if let Some(current) = config.current_season {
+            if (config.season_start_at..config.season_end_at).contains(&game.finished_at) {
+                require!(
+                    ctx.accounts.season.as_ref().map(|season| season.season_id) == Some(current),
+                    GameError::SeasonRequired
+                );
+            }
+        }
+
+        let mut season = ctx.accounts.season.as_deref_mut();
+        if let Some(season) = season.as_deref() {
+            require!(season.is_open(game.finished_at), GameError::SeasonNotOpen);
+        }
+
+        let count = game.player_count as usize;
+        require!(ctx.remaining_accounts.len() == count * 2, GameError::InvalidPlayerAccounts);
+        let (state_infos, profile_infos) = ctx.remaining_accounts.split_at(count);
+        let players = load_player_states(game, state_infos)?;
+        let mut profiles = load_profiles(&players, profile_infos)?;
+
+        let placements = placements(game, &players);
+        let ratings: Vec<u32> = profiles.iter().map(|profile| profile.rating).collect();
+        let teams: Vec<u8> = players.iter().map(|ps| ps.team).collect();
+        let deltas = elo_deltas(&ratings, &placements, &teams);
+
+        for ((ps, profile), delta) in players.iter().zip(profiles.iter_mut()).zip(deltas) {
+            profile.rating = (profile.rating as i64 + delta as i64).max(MIN_RATING as i64) as u32;
+            profile.games_played += 1;
+            match game.winning_team {
+                Some(team) if team == ps.team => profile.wins += 1,
+                Some(_) => profile.losses += 1,
+                None => profile.draws += 1,
+            }
+            profile.total_score += ps.score;
+            profile.last_game_id = Some(game_id);
+
+            if let Some(season) = season.as_deref_mut() {
+                if profile.season_id != season.season_id {
+                    profile.season_id = season.season_id;
+                    profile.season_points = 0;
+                    profile.season_wins = 0;
+                }
+                match game.winning_team {
+                    Some(team) if team == ps.team => {
+                        profile.season_points += SEASON_WIN_POINTS;
+                        profile.season_wins += 1;
+                    }
+                    Some(_) => {}
+                    None => profile.season_points += SEASON_DRAW_POINTS,
+                }
+                season.record(profile);
+            }
+
+            emit!(RatingChanged { game_id, player: profile.player, rating: profile.rating, delta });
+            msg!("{} rated {} ({:+})", profile.player, profile.rating, delta);
+            profile.exit(&crate::ID)?;
+        }
+
+        game.ratings_settled = true;
+        Ok(())
+    }
+
+    /// Create the program config singleton. Only the program's upgrade authority can,
+    /// and it becomes the first admin.
+    pub fn initialize_program_config(ctx: Context<InitializeProgramConfig>) -> Result<()> {
+        let config = &mut ctx.accounts.program_config;
+        config.admin = ctx.accounts.authority.key();
+        config.pending_admin = None;
+        config.paused = false;
+        config.fee_bps = 0;
+        config.current_season = None;
+        config.season_start_at = 0;
+        config.season_end_at = 0;
+        config.bump = ctx.bumps.program_config;
+
+        msg!("Program config initialized, admin {}", config.admin);
+        Ok(())
+    }
+
+    /// Start handing admin over to `new_admin`, who must call `accept_admin`
+    pub fn transfer_admin(ctx: Context<AdminOnly>, new_admin: Pubkey) -> Result<()> {
+        let config = &mut ctx.accounts.program_config;
+        config.pending_admin = Some(new_admin);
+
+        msg!("Admin transfer to {} started", new_admin);
+        Ok(())
+    }
+
+    /// Complete an admin transfer (pending admin only)
+    pub fn accept_admin(ctx: Context<AcceptAdmin>) -> Result<()> {
+        let config = &mut ctx.accounts.program_config;
+        let new_admin = ctx.accounts.new_admin.key();
+        require!(config.pending_admin == Some(new_admin), GameError::NotPendingAdmin);
+
+        let previous_admin = config.admin;
+        config.admin = new_admin;
+        config.pending_admin = None;
+
+        emit!(AdminChanged { previous_admin, new_admin });
+        msg!("Admin changed from {} to {}", previous_admin, new_admin);
+        Ok(())
+    }
+
+    /// Pause or resume new games. Running games can still be played, settled and claimed.
+    pub fn set_paused(ctx: Context<AdminOnly>, paused: bool) -> Result<()> {
+        ctx.accounts.program_config.paused = paused;
+
+        emit!(ProgramPauseChanged { paused });
+        msg!("Program {}", if paused { "paused" } else { "resumed" });
+        Ok(())
+    }
+
+    /// Emergency: end an unfinished game as a refund so every player can claim their stake back.
+    /// A delegated game is refunded in the ER, which then lets `undelegate_game` return it.
+    pub fn force_refund(ctx: Context<ForceRefund>, game_id: u64) -> Result<()> {
+        let game = &mut ctx.accounts.game;
+        require!(game.status != GameStatus::Finished, GameError::GameAlreadyFinished);
+
+        game.refunded = true;
+        game.finish(None, Clock::get()?.unix_timestamp);
+
+        emit!(GameRefunded { game_id, player_count: game.player_count, stake_amount: game.stake_amount });
+        msg!("Game {} force refunded ({} players)", game_id, game.player_count);
+        Ok(())
+    }
+
+    /// Create the treasury that collects protocol fees (admin only)
+    pub fn initialize_treasury(ctx: Context<InitializeTreasury>) -> Result<()> {
+        let treasury = &mut ctx.accounts.treasury;
+        treasury.total_collected = 0;
+        treasury.total_withdrawn = 0;
+        treasury.bump = ctx.bumps.treasury;
+
+        msg!("Treasury initialized");
+        Ok(())
+    }
+
+    /// Create the game counter (admin only); IDs start at FIRST_ASSIGNED_GAME_ID
+    pub fn initialize_game_counter(ctx: Context<InitializeGameCounter>) -> Result<()> {
+        let counter = &mut ctx.accounts.game_counter;
+        counter.next_id = FIRST_ASSIGNED_GAME_ID;
+        counter.bump = ctx.bumps.game_counter;
+
+        msg!("Game counter initialized at {}", counter.next_id);
+        Ok(())
+    }
+
+    /// Set the protocol fee taken from the pot of staked games created from now on.
+    /// A non-zero fee needs the treasury to exist, or claims could not pay it.
+    pub fn set_protocol_fee(ctx: Context<SetProtocolFee>, fee_bps: u16) -> Result<()> {
+        require!(fee_bps <= MAX_PROTOCOL_FEE_BPS, GameError::FeeTooHigh);
+        require!(fee_bps == 0 || ctx.accounts.treasury.is_some(), GameError::TreasuryRequired);
+        ctx.accounts.program_config.fee_bps = fee_bps;
+
+        msg!("Protocol fee set to {} bps", fee_bps);
+        Ok(())
+    }
+
+    /// Send collected fees to `recipient` (admin only); the treasury keeps its rent reserve
+    pub fn withdraw_treasury(ctx: Context<WithdrawTreasury>, amount: u64) -> Result<()> {
+        let treasury = &mut ctx.accounts.treasury;
+        let treasury_info = treasury.to_account_info();
+        let reserve = Rent::get()?.minimum_balance(treasury_info.data_len());
+        require!(
+            treasury_info.lamports().saturating_sub(reserve) >= amount,
+            GameError::InsufficientTreasury
+        );
+
+        **treasury_info.try_borrow_mut_lamports()? -= amount;
+        **ctx.accounts.recipient.try_borrow_mut_lamports()? += amount;
+        treasury.total_withdrawn += amount;
+
+        let recipient = ctx.accounts.recipient.key();
+        emit!(TreasuryWithdrawn { amount, recipient });
+        msg!("Withdrew {} lamports from the treasury to {}", amount, recipient);
+        Ok(())
+    }
+
+    /// Open season `season_id` between two timestamps, with `prize_pool` lamports of the
+    /// treasury promised to the top ranks (admin only)
+    pub fn create_season(
+        ctx: Context<CreateSeason>,
+        season_id: u32,
+        start_at: i64,
+        end_at: i64,
+        prize_pool: u64,
+    ) -> Result<()> {
+        let config = &mut ctx.accounts.program_config;
+        require!(start_at < end_at && start_at >= config.season_end_at, GameError::InvalidSeason);
+        config.current_season = Some(season_id);
+        config.season_start_at = start_at;
+        config.season_end_at = end_at;
+
+        let season = &mut ctx.accounts.season;
+        season.season_id = season_id;
+        season.start_at = start_at;
+        season.end_at = end_at;
+        season.prize_pool = prize_pool;
+        season.closed = false;
+        season.entry_count = 0;
+        season.entries = [LeaderboardEntry::default(); SEASON_BOARD_SIZE];
+        season.bump = ctx.bumps.season;
+
+        msg!("Season {} runs {} to {} (prize pool {})", season_id, start_at, end_at, prize_pool);
+        Ok(())
+    }
+
+    /// Freeze an ended season's board and, if `distribute`, pay its prize pool from the treasury
+    /// to the top ranks (admin only). Remaining accounts when distributing: the wallets of the
+    /// paid ranks in board order, writable.
+    pub fn close_season(ctx: Context<CloseSeason>, season_id: u32, distribute: bool) -> Result<()> {
+        let season = &mut ctx.accounts.season;
+        require!(!season.closed, GameError::SeasonClosed);
+        require!(Clock::get()?.unix_timestamp >= season.end_at, GameError::SeasonNotEnded);
+
+        let mut paid = 0;
+        if distribute {
+            let ranks = (season.entry_count as usize).min(SEASON_PRIZE_SHARES_BPS.len());
+            require!(ctx.remaining_accounts.len() == ranks, GameError::InvalidPlayerAccounts);
+
+            let treasury_info = ctx.accounts.treasury.to_account_info();
+            let reserve = Rent::get()?.minimum_balance(treasury_info.data_len());
+            let prizes: Vec<u64> = SEASON_PRIZE_SHARES_BPS[..ranks]
+                .iter()
+                .map(|&share| (season.prize_pool as u128 * share as u128 / BPS_DENOMINATOR as u128) as u64)
+                .collect();
+            let total: u64 = prizes.iter().sum();
+            require!(
+                treasury_info.lamports().saturating_sub(reserve) >= total,
+                GameError::InsufficientTreasury
+            );
+
+            for (rank, (info, prize)) in ctx.remaining_accounts.iter().zip(prizes).enumerate() {
+                require!(
+                    info.is_writable && info.key() == season.entries[rank].player,
+                    GameError::InvalidPlayerAccounts
+                );
+                **treasury_info.try_borrow_mut_lamports()? -= prize;
+                **info.try_borrow_mut_lamports()? += prize;
+                msg!("Season {} rank {} paid {} lamports", season_id, rank + 1, prize);
+            }
+            ctx.accounts.treasury.total_withdrawn += total;
+            paid = total;
+        }
+
+        season.closed = true;
+        // A closed season's board is frozen, so stop requiring it in settle_ratings
+        let config = &mut ctx.accounts.program_config;
+        if config.current_season == Some(season_id) {
+            config.current_season = None;
+        }
+        emit!(SeasonClosed {
+            season_id,
+            leader: (season.entry_count > 0).then(|| season.entries[0].player),
+            prizes_paid: paid,
+        });
+        msg!("Season {} closed", season_id);
+        Ok(())
+    }
+
+    /// Publish balance preset `version` (program admin only).
+    /// Presets are immutable: publish a new version to change the economy.
+    pub fn create_game_config(ctx: Context<CreateGameConfig>, version: u32, balance: Balance) -> Result<()> {
+        require!(version > 0, GameError::InvalidBalance);
+        require!(engine::Balance::from(balance).is_valid(), GameError::InvalidBalance);
+
+        let config = &mut ctx.accounts.game_config;
+        config.version = version;
+        config.admin = ctx.accounts.admin.key();
+        config.balance = balance;
+        config.retired = false;
+        config.created_at = Clock::get()?.unix_timestamp;
+        config.bump = ctx.bumps.game_config;
+
+        msg!("Game config v{} published: {:?}", version, balance);
+        Ok(())
+    }
+
+    /// Stop new games from using preset `version` (program admin only). Running games keep their snapshot.
+    pub fn retire_game_config(ctx: Context<RetireGameConfig>, version: u32) -> Result<()> {
+        let config = &mut ctx.accounts.game_config;
+        config.retired = true;
+
+        msg!("Game config v{} retired", version);
+        Ok(())
+    }
+
+    /// Attach a replay log to a game still in its lobby (creator only),
+    /// so the log covers every action from the initial state
+    pub fn create_action_log(ctx: Context<CreateActionLog>, game_id: u64) -> Result<()> {
+        let game = &mut ctx.accounts.game;
+        require!(game.status == GameStatus::Lobby, GameError::GameNotInLobby);
+        require!(game.creator == ctx.accounts.creator.key(), GameError::NotCreator);
+
+        let mut log = ctx.accounts.action_log.load_init()?;
+        log.game_id = game_id;
+        log.count = 0;
+        game.action_log = true;
+
+        msg!("Game {} action log created ({} records)", game_id, ACTION_LOG_CAPACITY);
+        Ok(())
+    }
+
+    /// Delegate game (and every PlayerState) to MagicBlock ER for real-time execution.
+    /// Remaining accounts: [player_state, buffer, delegation_record, delegation_metadata] per player.
+    /// Games with an action log append one more [action_log, buffer, delegation_record, delegation_metadata].
+    /// Then one such group per SessionToken or AgentAuthority of this game that should keep working
+    /// in the ER, since acting through them updates their spend and rate-limit counters.
+    pub fn delegate_game<'info>(
+        ctx: Context<'_, '_, 'info, 'info, DelegateGame<'info>>,
+        game_id: u64,
+        commit_frequency_ms: u32,
+    ) -> Result<()> {
+        let mut game = (*ctx.accounts.game).clone();
+        let game_info = ctx.accounts.game.to_account_info();
+
+        require!(game.status == GameStatus::Active, GameError::GameNotActive);
+        require!(game.creator == ctx.accounts.payer.key(), GameError::NotCreator);
+        require!(commit_frequency_ms > 0, GameError::InvalidCommitFrequency);
+        let log_accounts = if game.action_log { 4 } else { 0 };
+        let fixed_accounts = game.player_count as usize * 4 + log_accounts;
+        require!(ctx.remaining_accounts.len() >= fixed_accounts, GameError::InvalidPlayerAccounts);
+        let session_chunks = &ctx.remaining_accounts[fixed_accounts..];
+        require!(session_chunks.len().is_multiple_of(4), GameError::InvalidDelegationAccounts);
+
+        // Record delegation settings before the state is copied to the rollup
+        game.delegated = true;
+        game.commit_frequency_ms = commit_frequency_ms;
+        game.delegated_sessions = (session_chunks.len() / 4) as u8;
+        game.try_serialize(&mut &mut game_info.try_borrow_mut_data()?[..])?;
+
+        let game_id_bytes = game_id.to_le_bytes();
+        DelegationAccounts {
+            payer: ctx.accounts.payer.to_account_info(),
+            pda: game_info,
+            owner_program: ctx.accounts.owner_program.to_account_info(),
+            buffer: ctx.accounts.buffer.to_account_info(),
+            delegation_record: ctx.accounts.delegation_record.to_account_info(),
+            delegation_metadata: ctx.accounts.delegation_metadata.to_account_info(),
+            delegation_program: ctx.accounts.delegation_program.to_account_info(),
+            system_program: ctx.accounts.system_program.to_account_info(),
+        }
+        .delegate(&[GAME_SEED, &game_id_bytes], game.bump, commit_frequency_ms)?;
+
+        for (i, chunk) in ctx.remaining_accounts.chunks(4).take(game.player_count as usize).enumerate() {
+            let ps = Account::<PlayerState>::try_from(&chunk[0])?;
+            require!(
+                ps.game_id == game_id && ps.player == game.players[i],
+                GameError::InvalidPlayerAccounts
+            );
+            let seeds: &[&[u8]] = &[PLAYER_SEED, &game_id_bytes, ps.player.as_ref()];
+            let expected = Pubkey::create_program_address(&[seeds, &[&[ps.bump]]].concat(), &crate::ID)
+                .map_err(|_| GameError::InvalidPlayerAccounts)?;
+            require_keys_eq!(chunk[0].key(), expected, GameError::InvalidPlayerAccounts);
+
+            DelegationAccounts {
+                payer: ctx.accounts.payer.to_account_info(),
+                pda: chunk[0].clone(),
+                owner_program: ctx.accounts.owner_program.to_account_info(),
+                buffer: chunk[1].clone(),
+                delegation_record: chunk[2].clone(),
+                delegation_metadata: chunk[3].clone(),
+                delegation_program: ctx.accounts.delegation_program.to_account_info(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+            }
+            .delegate(seeds, ps.bump, commit_frequency_ms)?;
+        }
+
+        // The action log follows the players so the ER can keep appending to it
+        if game.action_log {
+            let chunk = &ctx.remaining_accounts[game.player_count as usize * 4..];
+            let seeds: &[&[u8]] = &[ACTION_LOG_SEED, &game_id_bytes];
+            let (expected, bump) = Pubkey::find_program_address(seeds, &crate::ID);
+            require_keys_eq!(chunk[0].key(), expected, GameError::InvalidPlayerAccounts);
+
+            DelegationAccounts {
+                payer: ctx.accounts.payer.to_account_info(),
+                pda: chunk[0].clone(),
+                owner_program: ctx.accounts.owner_program.to_account_info(),
+                buffer: chunk[1].clone(),
+                delegation_record: chunk[2].clone(),
+                delegation_metadata: chunk[3].clone(),
+                delegation_program: ctx.accounts.delegation_program.to_account_info(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+            }
+            .delegate(seeds, bump, commit_frequency_ms)?;
+        }
+
+        for chunk in session_chunks.chunks(4) {
+            let (seeds, bump) = session_seeds(&chunk[0], game_id)?;
+            let seeds: Vec<&[u8]> = seeds.iter().map(|seed| seed.as_slice()).collect();
+            DelegationAccounts {
+                payer: ctx.accounts.payer.to_account_info(),
+                pda: chunk[0].clone(),
+                owner_program: ctx.accounts.owner_program.to_account_info(),
+                buffer: chunk[1].clone(),
+                delegation_record: chunk[2].clone(),
+                delegation_metadata: chunk[3].clone(),
+                delegation_program: ctx.accounts.delegation_program.to_account_info(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+            }
+            .delegate(&seeds, bump, commit_frequency_ms)?;
+        }
+
+        emit!(GameDelegated {
+            game_id,
+            commit_frequency_ms,
+            player_count: game.player_count,
+        });
+        msg!("Game {} delegated to ER! (commit every {}ms)", game_id, commit_frequency_ms);
+        Ok(())
+    }
+
+    /// Checkpoint game and player state from the ER back to the base layer.
+    /// Remaining accounts: every player's PlayerState, then every delegated session / agent account.
+    pub fn commit_game<'info>(
+        ctx: Context<'_, '_, 'info, 'info, CommitGame<'info>>,
+        _game_id: u64,
+    ) -> Result<()> {
+        let game = &ctx.accounts.game;
+        require!(game.delegated, GameError::NotDelegated);
+        let accounts = delegated_accounts(game, ctx.remaining_accounts, &ctx.accounts.action_log)?;
+
+        game.exit(&crate::ID)?;
+        schedule_commit(
+            &ctx.accounts.payer.to_account_info(),
+            &ctx.accounts.magic_context.to_account_info(),
+            &ctx.accounts.magic_program.to_account_info(),
+            &game.to_account_info(),
+            &accounts,
+            false,
+        )?;
+
+        msg!("Game {} committed at turn {}", game.game_id, game.turn);
+        Ok(())
+    }
+
+    /// Commit the final state of a finished game and return every account to the base layer
+    /// for settlement (permissionless). Remaining accounts: every player's PlayerState, then
+    /// every delegated session / agent account.
+    pub fn undelegate_game<'info>(
+        ctx: Context<'_, '_, 'info, 'info, CommitGame<'info>>,
+        _game_id: u64,
+    ) -> Result<()> {
+        let game = &mut ctx.accounts.game;
+        require!(game.delegated, GameError::NotDelegated);
+        require!(game.status == GameStatus::Finished, GameError::GameNotFinished);
+        let accounts = delegated_accounts(game, ctx.remaining_accounts, &ctx.accounts.action_log)?;
+
+        game.delegated = false;
+        game.delegated_sessions = 0;
+        game.exit(&crate::ID)?;
+        schedule_commit(
+            &ctx.accounts.payer.to_account_info(),
+            &ctx.accounts.magic_context.to_account_info(),
+            &ctx.accounts.magic_program.to_account_info(),
+            &game.to_account_info(),
+            &accounts,
+            true,
+        )?;
+
+        msg!("Game {} committed and undelegated", game.game_id);
+        Ok(())
+    }
+
+    /// Called back by the delegation program on undelegation to restore an account
+    /// from the buffer it hands over
+    pub fn process_undelegation(ctx: Context<ProcessUndelegation>, account_seeds: Vec<Vec<u8>>) -> Result<()> {
+        let buffer = &ctx.accounts.buffer;
+        require!(buffer.is_signer, GameError::InvalidDelegationAccounts);
+        require_keys_eq!(*buffer.owner, DELEGATION_PROGRAM_ID, GameError::InvalidDelegationAccounts);
+
+        let seeds: Vec<&[u8]> = account_seeds.iter().map(|seed| seed.as_slice()).collect();
+        let (expected, bump) = Pubkey::find_program_address(&seeds, &crate::ID);
+        require_keys_eq!(ctx.accounts.base_account.key(), expected, GameError::InvalidDelegationAccounts);
+
+        let bump_seed = [bump];
+        let mut signer_seeds = seeds.clone();
+        signer_seeds.push(&bump_seed);
+
+        // Re-create the original account and restore its data
+        let base_account = ctx.accounts.base_account.to_account_info();
+        let space = buffer.data_len();
+        anchor_lang::system_program::create_account(
+            CpiContext::new_with_signer(
+                ctx.accounts.system_program.to_account_info(),
+                anchor_lang::system_program::CreateAccount {
+                    from: ctx.accounts.payer.to_account_info(),
+                    to: base_account.clone(),
+                },
+                &[&signer_seeds],
+            ),
+            Rent::get()?.minimum_balance(space),
+            space as u64,
+            &crate::ID,
+        )?;
+        base_account.try_borrow_mut_data()?.copy_from_slice(&buffer.try_borrow_data()?);
+
+        msg!("Account {} undelegated", expected);
+        Ok(())
+    }
+}
+
+// ==================== ACCOUNTS ====================
+
+#[derive(Accounts)]
 #[instruction(game_id: u64)]
-pub struct CollectResources<'info> {
+pub struct CreateGame<'info> {
+    #[account(
+        init,
+        payer = creator,
+        space = 8 + Game::LEN,
+        seeds = [GAME_SEED, &game_id.to_le_bytes()],
+        bump
+    )]
+    pub game: Account<'info, Game>,
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    #[account(seeds = [PROGRAM_CONFIG_SEED], bump = program_config.bump)]
+    pub program_config: Account<'info, ProgramConfig>,
+    /// Balance preset to snapshot; the stock balance is used when omitted
+    #[account(seeds = [GAME_CONFIG_SEED, &game_config.version.to_le_bytes()], bump = game_config.bump)]
+    pub game_config: Option<Account<'info, GameConfig>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CreateNextGame<'info> {
+    #[account(mut, seeds = [GAME_COUNTER_SEED], bump = game_counter.bump)]
+    pub game_counter: Account<'info, GameCounter>,
+    #[account(
+        init,
+        payer = creator,
+        space = 8 + Game::LEN,
+        seeds = [GAME_SEED, &game_counter.next_id.to_le_bytes()],
+        bump
+    )]
+    pub game: Account<'info, Game>,
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    #[account(seeds = [PROGRAM_CONFIG_SEED], bump = program_config.bump)]
+    pub program_config: Account<'info, ProgramConfig>,
+    /// Balance preset to snapshot; the stock balance is used when omitted
+    #[account(seeds = [GAME_CONFIG_SEED, &game_config.version.to_le_bytes()], bump = game_config.bump)]
+    pub game_config: Option<Account<'info, GameConfig>>,
+    /// Records the new ID as the creator's last_created_game_id when passed
+    #[account(mut, seeds = [PROFILE_SEED, creator.key().as_ref()], bump = creator_profile.bump)]
+    pub creator_profile: Option<Account<'info, PlayerProfile>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(game_id: u64)]
+pub struct JoinGame<'info> {
+    #[account(
+        mut,
+        seeds = [GAME_SEED, &game_id.to_le_bytes()],
+        bump
+    )]
+    pub game: Account<'info, Game>,
+    #[account(
+        init,
+        payer = player,
+        space = 8 + PlayerState::LEN,
+        seeds = [PLAYER_SEED, &game_id.to_le_bytes(), player.key().as_ref()],
+        bump
+    )]
+    pub player_state: Account<'info, PlayerState>,
+    #[account(mut)]
+    pub player: Signer<'info>,
+    #[account(seeds = [PROGRAM_CONFIG_SEED], bump = program_config.bump)]
+    pub program_config: Account<'info, ProgramConfig>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct StartGame<'info> {
+    #[account(mut)]
+    pub game: Account<'info, Game>,
+    #[account(
+        init,
+        payer = creator,
+        space = 8 + Market::LEN,
+        seeds = [MARKET_SEED, &game.game_id.to_le_bytes()],
+        bump
+    )]
+    pub market: Account<'info, Market>,
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(game_id: u64)]
+pub struct MoveUnits<'info> {
+    #[account(mut, seeds = [GAME_SEED, &game_id.to_le_bytes()], bump)]
+    pub game: Account<'info, Game>,
+    #[account(mut, seeds = [PLAYER_SEED, &game_id.to_le_bytes(), player_state.player.as_ref()], bump = player_state.bump)]
+    pub player_state: Account<'info, PlayerState>,
+    /// Player wallet, or a session key / agent backed by `session_token` / `agent_authority`
+    #[account(mut)]
+    pub player: Signer<'info>,
+    #[account(mut)]
+    pub session_token: Option<Account<'info, SessionToken>>,
+    #[account(mut)]
+    pub agent_authority: Option<Account<'info, AgentAuthority>>,
+    /// Replay log, required once the game has one
+    #[account(mut, seeds = [ACTION_LOG_SEED, &game_id.to_le_bytes()], bump)]
+    pub action_log: Option<AccountLoader<'info, ActionLog>>,
+}
+
+#[derive(Accounts)]
+#[instruction(game_id: u64)]
+pub struct BuildDefense<'info> {
+    #[account(mut, seeds = [GAME_SEED, &game_id.to_le_bytes()], bump)]
+    pub game: Account<'info, Game>,
+    #[account(mut, seeds = [PLAYER_SEED, &game_id.to_le_bytes(), player_state.player.as_ref()], bump = player_state.bump)]
+    pub player_state: Account<'info, PlayerState>,
+    /// Player wallet, or a session key / agent backed by `session_token` / `agent_authority`
+    #[account(mut)]
+    pub player: Signer<'info>,
+    #[account(mut)]
+    pub session_token: Option<Account<'info, SessionToken>>,
+    #[account(mut)]
+    pub agent_authority: Option<Account<'info, AgentAuthority>>,
+    /// Replay log, required once the game has one
+    #[account(mut, seeds = [ACTION_LOG_SEED, &game_id.to_le_bytes()], bump)]
+    pub action_log: Option<AccountLoader<'info, ActionLog>>,
+}
+
+#[derive(Accounts)]
+#[instruction(game_id: u64)]
+pub struct TrainUnits<'info> {
+    #[account(mut, seeds = [GAME_SEED, &game_id.to_le_bytes()], bump)]
+    pub game: Account<'info, Game>,
+    #[account(mut, seeds = [PLAYER_SEED, &game_id.to_le_bytes(), player_state.player.as_ref()], bump = player_state.bump)]
+    pub player_state: Account<'info, PlayerState>,
+    /// Player wallet, or a session key / agent backed by `session_token` / `agent_authority`
+    #[account(mut)]
+    pub player: Signer<'info>,
+    #[account(mut)]
+    pub session_token: Option<Account<'info, SessionToken>>,
+    #[account(mut)]
+    pub agent_authority: Option<Account<'info, AgentAuthority>>,
+    /// Replay log, required once the game has one
+    #[account(mut, seeds = [ACTION_LOG_SEED, &game_id.to_le_bytes()], bump)]
+    pub action_log: Option<AccountLoader<'info, ActionLog>>,
+}
+
+#[derive(Accounts)]
+#[instruction(game_id: u64)]
+pub struct CollectResources<'info> {
+    #[account(mut, seeds = [GAME_SEED, &game_id.to_le_bytes()], bump)]
+    pub game: Account<'info, Game>,
+    #[account(mut, seeds = [PLAYER_SEED, &game_id.to_le_bytes(), player_state.player.as_ref()], bump = player_state.bump)]
+    pub player_state: Account<'info, PlayerState>,
+    /// Player wallet, or a session key / agent backed by `session_token` / `agent_authority`
+    #[account(mut)]
+    pub player: Signer<'info>,
+    #[account(mut)]
+    pub session_token: Option<Account<'info, SessionToken>>,
+    #[account(mut)]
+    pub agent_authority: Option<Account<'info, AgentAuthority>>,
+    /// Replay log, required once the game has one
+    #[account(mut, seeds = [ACTION_LOG_SEED, &game_id.to_le_bytes()], bump)]
+    pub action_log: Option<AccountLoader<'info, ActionLog>>,
+}
+
+#[derive(Accounts)]
+#[instruction(game_id: u64)]
+pub struct SetStrategy<'info> {
+    #[account(mut, seeds = [PLAYER_SEED, &game_id.to_le_bytes(), player_state.player.as_ref()], bump = player_state.bump)]
+    pub player_state: Account<'info, PlayerState>,
+    /// Player wallet, or a session key backed by `session_token`
+    #[account(mut)]
+    pub player: Signer<'info>,
+    #[account(mut)]
+    pub session_token: Option<Account<'info, SessionToken>>,
+}
+
+#[derive(Accounts)]
+#[instruction(game_id: u64)]
+pub struct AutoplayTick<'info> {
+    #[account(mut, seeds = [GAME_SEED, &game_id.to_le_bytes()], bump)]
+    pub game: Account<'info, Game>,
+    #[account(
+        mut,
+        seeds = [PLAYER_SEED, &game_id.to_le_bytes(), player_state.player.as_ref()],
+        bump = player_state.bump
+    )]
+    pub player_state: Account<'info, PlayerState>,
+    /// Replay log, required once the game has one
+    #[account(mut, seeds = [ACTION_LOG_SEED, &game_id.to_le_bytes()], bump)]
+    pub action_log: Option<AccountLoader<'info, ActionLog>>,
+}
+
+#[derive(Accounts)]
+#[instruction(game_id: u64)]
+pub struct SetAutoplay<'info> {
     #[account(seeds = [GAME_SEED, &game_id.to_le_bytes()], bump)]
     pub game: Account<'info, Game>,
-    #[account(mut, seeds = [PLAYER_SEED, &game_id.to_le_bytes(), player.key().as_ref()], bump)]
+    #[account(mut, seeds = [PLAYER_SEED, &game_id.to_le_bytes(), player_state.player.as_ref()], bump = player_state.bump)]
+    pub player_state: Account<'info, PlayerState>,
+    /// Player wallet, or a session key backed by `session_token`
+    #[account(mut)]
+    pub player: Signer<'info>,
+    #[account(mut)]
+    pub session_token: Option<Account<'info, SessionToken>>,
+    /// Replay log, required once the game has one
+    #[account(mut, seeds = [ACTION_LOG_SEED, &game_id.to_le_bytes()], bump)]
+    pub action_log: Option<AccountLoader<'info, ActionLog>>,
+}
+
+#[derive(Accounts)]
+#[instruction(game_id: u64, offer_id: u64)]
+pub struct CreateTradeOffer<'info> {
+    #[account(seeds = [GAME_SEED, &game_id.to_le_bytes()], bump)]
+    pub game: Account<'info, Game>,
+    #[account(mut, seeds = [PLAYER_SEED, &game_id.to_le_bytes(), player_state.player.as_ref()], bump = player_state.bump)]
+    pub player_state: Account<'info, PlayerState>,
+    #[account(
+        init,
+        payer = player,
+        space = 8 + TradeOffer::LEN,
+        seeds = [OFFER_SEED, &game_id.to_le_bytes(), player_state.player.as_ref(), &offer_id.to_le_bytes()],
+        bump
+    )]
+    pub offer: Account<'info, TradeOffer>,
+    /// Player wallet, or a session key backed by `session_token`
+    #[account(mut)]
+    pub player: Signer<'info>,
+    #[account(mut)]
+    pub session_token: Option<Account<'info, SessionToken>>,
+    /// Replay log, required once the game has one
+    #[account(mut, seeds = [ACTION_LOG_SEED, &game_id.to_le_bytes()], bump)]
+    pub action_log: Option<AccountLoader<'info, ActionLog>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(game_id: u64, offer_id: u64)]
+pub struct AcceptTradeOffer<'info> {
+    #[account(seeds = [GAME_SEED, &game_id.to_le_bytes()], bump)]
+    pub game: Account<'info, Game>,
+    #[account(
+        mut,
+        close = maker,
+        seeds = [OFFER_SEED, &game_id.to_le_bytes(), maker.key().as_ref(), &offer_id.to_le_bytes()],
+        bump
+    )]
+    pub offer: Account<'info, TradeOffer>,
+    #[account(mut, seeds = [PLAYER_SEED, &game_id.to_le_bytes(), maker.key().as_ref()], bump)]
+    pub maker_state: Account<'info, PlayerState>,
+    #[account(mut, address = offer.maker)]
+    pub maker: SystemAccount<'info>,
+    #[account(mut, seeds = [PLAYER_SEED, &game_id.to_le_bytes(), taker_state.player.as_ref()], bump = taker_state.bump)]
+    pub taker_state: Account<'info, PlayerState>,
+    /// Taker wallet, or a session key backed by `session_token`
+    #[account(mut)]
+    pub taker: Signer<'info>,
+    #[account(mut)]
+    pub session_token: Option<Account<'info, SessionToken>>,
+    /// Replay log, required once the game has one
+    #[account(mut, seeds = [ACTION_LOG_SEED, &game_id.to_le_bytes()], bump)]
+    pub action_log: Option<AccountLoader<'info, ActionLog>>,
+}
+
+#[derive(Accounts)]
+#[instruction(game_id: u64, offer_id: u64)]
+pub struct CancelTradeOffer<'info> {
+    #[account(
+        mut,
+        close = maker,
+        seeds = [OFFER_SEED, &game_id.to_le_bytes(), maker.key().as_ref(), &offer_id.to_le_bytes()],
+        bump
+    )]
+    pub offer: Account<'info, TradeOffer>,
+    #[account(seeds = [GAME_SEED, &game_id.to_le_bytes()], bump)]
+    pub game: Account<'info, Game>,
+    #[account(mut, seeds = [PLAYER_SEED, &game_id.to_le_bytes(), maker.key().as_ref()], bump)]
+    pub maker_state: Account<'info, PlayerState>,
+    #[account(mut, address = offer.maker)]
+    pub maker: SystemAccount<'info>,
+    /// Maker wallet or a session key backed by `session_token`; anyone once the offer expired
+    pub caller: Signer<'info>,
+    pub session_token: Option<Account<'info, SessionToken>>,
+    /// Replay log, required once the game has one
+    #[account(mut, seeds = [ACTION_LOG_SEED, &game_id.to_le_bytes()], bump)]
+    pub action_log: Option<AccountLoader<'info, ActionLog>>,
+}
+
+#[derive(Accounts)]
+#[instruction(game_id: u64)]
+pub struct Swap<'info> {
+    #[account(seeds = [GAME_SEED, &game_id.to_le_bytes()], bump)]
+    pub game: Account<'info, Game>,
+    #[account(mut, seeds = [MARKET_SEED, &game_id.to_le_bytes()], bump)]
+    pub market: Account<'info, Market>,
+    #[account(mut, seeds = [PLAYER_SEED, &game_id.to_le_bytes(), player_state.player.as_ref()], bump = player_state.bump)]
+    pub player_state: Account<'info, PlayerState>,
+    /// Player wallet, or a session key backed by `session_token`
+    #[account(mut)]
+    pub player: Signer<'info>,
+    #[account(mut)]
+    pub session_token: Option<Account<'info, SessionToken>>,
+    /// Replay log, required once the game has one
+    #[account(mut, seeds = [ACTION_LOG_SEED, &game_id.to_le_bytes()], bump)]
+    pub action_log: Option<AccountLoader<'info, ActionLog>>,
+}
+
+#[derive(Accounts)]
+#[instruction(game_id: u64)]
+pub struct ProposeTreaty<'info> {
+    #[account(mut, seeds = [GAME_SEED, &game_id.to_le_bytes()], bump)]
+    pub game: Account<'info, Game>,
+    #[account(seeds = [PLAYER_SEED, &game_id.to_le_bytes(), player_state.player.as_ref()], bump = player_state.bump)]
+    pub player_state: Account<'info, PlayerState>,
+    /// Player wallet, or a session key backed by `session_token`
+    #[account(mut)]
+    pub player: Signer<'info>,
+    #[account(mut)]
+    pub session_token: Option<Account<'info, SessionToken>>,
+    /// Replay log, required once the game has one
+    #[account(mut, seeds = [ACTION_LOG_SEED, &game_id.to_le_bytes()], bump)]
+    pub action_log: Option<AccountLoader<'info, ActionLog>>,
+}
+
+#[derive(Accounts)]
+#[instruction(game_id: u64)]
+pub struct AcceptTreaty<'info> {
+    #[account(mut, seeds = [GAME_SEED, &game_id.to_le_bytes()], bump)]
+    pub game: Account<'info, Game>,
+    #[account(seeds = [PLAYER_SEED, &game_id.to_le_bytes(), player_state.player.as_ref()], bump = player_state.bump)]
+    pub player_state: Account<'info, PlayerState>,
+    /// Player wallet, or a session key backed by `session_token`
+    #[account(mut)]
+    pub player: Signer<'info>,
+    #[account(mut)]
+    pub session_token: Option<Account<'info, SessionToken>>,
+    /// Replay log, required once the game has one
+    #[account(mut, seeds = [ACTION_LOG_SEED, &game_id.to_le_bytes()], bump)]
+    pub action_log: Option<AccountLoader<'info, ActionLog>>,
+}
+
+#[derive(Accounts)]
+#[instruction(game_id: u64)]
+pub struct BreakTreaty<'info> {
+    #[account(mut, seeds = [GAME_SEED, &game_id.to_le_bytes()], bump)]
+    pub game: Account<'info, Game>,
+    #[account(mut, seeds = [PLAYER_SEED, &game_id.to_le_bytes(), player_state.player.as_ref()], bump = player_state.bump)]
+    pub player_state: Account<'info, PlayerState>,
+    /// Player wallet, or a session key backed by `session_token`
+    #[account(mut)]
+    pub player: Signer<'info>,
+    #[account(mut)]
+    pub session_token: Option<Account<'info, SessionToken>>,
+    /// Replay log, required once the game has one
+    #[account(mut, seeds = [ACTION_LOG_SEED, &game_id.to_le_bytes()], bump)]
+    pub action_log: Option<AccountLoader<'info, ActionLog>>,
+}
+
+#[derive(Accounts)]
+#[instruction(game_id: u64, session_key: Pubkey)]
+pub struct CreateSession<'info> {
+    #[account(seeds = [PLAYER_SEED, &game_id.to_le_bytes(), player.key().as_ref()], bump)]
+    pub player_state: Account<'info, PlayerState>,
+    #[account(
+        init,
+        payer = player,
+        space = 8 + SessionToken::LEN,
+        seeds = [SESSION_SEED, &game_id.to_le_bytes(), player.key().as_ref(), session_key.as_ref()],
+        bump
+    )]
+    pub session_token: Account<'info, SessionToken>,
+    /// CHECK: Session keypair, only receives the fee top-up
+    #[account(mut, address = session_key)]
+    pub session_signer: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub player: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(game_id: u64, session_key: Pubkey)]
+pub struct RevokeSession<'info> {
+    #[account(
+        mut,
+        seeds = [SESSION_SEED, &game_id.to_le_bytes(), player.key().as_ref(), session_key.as_ref()],
+        bump = session_token.bump
+    )]
+    pub session_token: Account<'info, SessionToken>,
+    pub player: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(game_id: u64, session_key: Pubkey)]
+pub struct CloseSession<'info> {
+    #[account(
+        mut,
+        close = player,
+        seeds = [SESSION_SEED, &game_id.to_le_bytes(), player.key().as_ref(), session_key.as_ref()],
+        bump = session_token.bump
+    )]
+    pub session_token: Account<'info, SessionToken>,
+    #[account(mut)]
+    pub player: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(game_id: u64)]
+pub struct RegisterAgent<'info> {
+    #[account(seeds = [PLAYER_SEED, &game_id.to_le_bytes(), player.key().as_ref()], bump)]
     pub player_state: Account<'info, PlayerState>,
+    #[account(
+        init,
+        payer = player,
+        space = 8 + AgentAuthority::LEN,
+        seeds = [AGENT_SEED, &game_id.to_le_bytes(), player.key().as_ref()],
+        bump
+    )]
+    pub agent_authority: Account<'info, AgentAuthority>,
     #[account(mut)]
     pub player: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(game_id: u64)]
+pub struct RevokeAgent<'info> {
+    #[account(
+        mut,
+        seeds = [AGENT_SEED, &game_id.to_le_bytes(), player.key().as_ref()],
+        bump = agent_authority.bump
+    )]
+    pub agent_authority: Account<'info, AgentAuthority>,
+    pub player: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(game_id: u64)]
+pub struct CloseAgent<'info> {
+    #[account(
+        mut,
+        close = player,
+        seeds = [AGENT_SEED, &game_id.to_le_bytes(), player.key().as_ref()],
+        bump = agent_authority.bump
+    )]
+    pub agent_authority: Account<'info, AgentAuthority>,
+    #[account(mut)]
+    pub player: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct EndGame<'info> {
+    #[account(mut)]
+    pub game: Account<'info, Game>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(game_id: u64)]
+pub struct CheckVictory<'info> {
+    #[account(mut, seeds = [GAME_SEED, &game_id.to_le_bytes()], bump)]
+    pub game: Account<'info, Game>,
+}
+
+#[derive(Accounts)]
+#[instruction(game_id: u64)]
+pub struct AdvanceStorm<'info> {
+    #[account(mut, seeds = [GAME_SEED, &game_id.to_le_bytes()], bump)]
+    pub game: Account<'info, Game>,
+    /// Replay log, required once the game has one
+    #[account(mut, seeds = [ACTION_LOG_SEED, &game_id.to_le_bytes()], bump)]
+    pub action_log: Option<AccountLoader<'info, ActionLog>>,
+}
+
+#[derive(Accounts)]
+#[instruction(game_id: u64)]
+pub struct BarbarianWave<'info> {
+    #[account(mut, seeds = [GAME_SEED, &game_id.to_le_bytes()], bump)]
+    pub game: Account<'info, Game>,
+    /// Replay log, required once the game has one
+    #[account(mut, seeds = [ACTION_LOG_SEED, &game_id.to_le_bytes()], bump)]
+    pub action_log: Option<AccountLoader<'info, ActionLog>>,
+}
+
+#[derive(Accounts)]
+#[instruction(game_id: u64)]
+pub struct ClaimWinnings<'info> {
+    #[account(mut, seeds = [GAME_SEED, &game_id.to_le_bytes()], bump)]
+    pub game: Account<'info, Game>,
+    #[account(mut, seeds = [PLAYER_SEED, &game_id.to_le_bytes(), player.key().as_ref()], bump)]
+    pub player_state: Account<'info, PlayerState>,
+    #[account(mut)]
+    pub player: Signer<'info>,
+    /// Only needed by the claim that sweeps the protocol fee
+    #[account(mut, seeds = [TREASURY_SEED], bump = treasury.bump)]
+    pub treasury: Option<Account<'info, Treasury>>,
+}
+
+#[derive(Accounts)]
+#[instruction(queue_id: u32)]
+pub struct CreateMatchQueue<'info> {
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + MatchQueue::LEN,
+        seeds = [QUEUE_SEED, &queue_id.to_le_bytes()],
+        bump
+    )]
+    pub match_queue: Account<'info, MatchQueue>,
+    #[account(seeds = [PROGRAM_CONFIG_SEED], bump = program_config.bump, has_one = admin @ GameError::NotAdmin)]
+    pub program_config: Account<'info, ProgramConfig>,
+    /// Balance preset for games formed from this queue; the stock balance when omitted
+    #[account(seeds = [GAME_CONFIG_SEED, &game_config.version.to_le_bytes()], bump = game_config.bump)]
+    pub game_config: Option<Account<'info, GameConfig>>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(queue_id: u32)]
+pub struct Enqueue<'info> {
+    #[account(mut, seeds = [QUEUE_SEED, &queue_id.to_le_bytes()], bump = match_queue.bump)]
+    pub match_queue: Account<'info, MatchQueue>,
+    #[account(seeds = [PROFILE_SEED, player.key().as_ref()], bump = player_profile.bump)]
+    pub player_profile: Account<'info, PlayerProfile>,
+    #[account(seeds = [PROGRAM_CONFIG_SEED], bump = program_config.bump)]
+    pub program_config: Account<'info, ProgramConfig>,
+    #[account(mut)]
+    pub player: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(queue_id: u32)]
+pub struct Dequeue<'info> {
+    #[account(mut, seeds = [QUEUE_SEED, &queue_id.to_le_bytes()], bump = match_queue.bump)]
+    pub match_queue: Account<'info, MatchQueue>,
+    #[account(mut)]
+    pub player: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(queue_id: u32)]
+pub struct FormMatch<'info> {
+    #[account(mut, seeds = [QUEUE_SEED, &queue_id.to_le_bytes()], bump = match_queue.bump)]
+    pub match_queue: Account<'info, MatchQueue>,
+    #[account(mut, seeds = [GAME_COUNTER_SEED], bump = game_counter.bump)]
+    pub game_counter: Account<'info, GameCounter>,
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Game::LEN,
+        seeds = [GAME_SEED, &game_counter.next_id.to_le_bytes()],
+        bump
+    )]
+    pub game: Account<'info, Game>,
+    #[account(seeds = [PROGRAM_CONFIG_SEED], bump = program_config.bump)]
+    pub program_config: Account<'info, ProgramConfig>,
+    /// Pays rent for the game and player accounts
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(player: Pubkey)]
+pub struct CreateProfile<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + PlayerProfile::LEN,
+        seeds = [PROFILE_SEED, player.as_ref()],
+        bump
+    )]
+    pub player_profile: Account<'info, PlayerProfile>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(game_id: u64)]
+pub struct SettleRatings<'info> {
+    #[account(mut, seeds = [GAME_SEED, &game_id.to_le_bytes()], bump)]
+    pub game: Account<'info, Game>,
+    /// Season the game counts toward; must be open when the game finished.
+    /// Required when the game finished inside the current season's window.
+    #[account(mut, seeds = [SEASON_SEED, &season.season_id.to_le_bytes()], bump = season.bump)]
+    pub season: Option<Account<'info, Season>>,
+    #[account(seeds = [PROGRAM_CONFIG_SEED], bump = program_config.bump)]
+    pub program_config: Account<'info, ProgramConfig>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeProgramConfig<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + ProgramConfig::LEN,
+        seeds = [PROGRAM_CONFIG_SEED],
+        bump
+    )]
+    pub program_config: Account<'info, ProgramConfig>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()))]
+    pub program: Program<'info, crate::program::Ghostnet>,
+    #[account(constraint = program_data.upgrade_authority_address == Some(authority.key()) @ GameError::NotAdmin)]
+    pub program_data: Account<'info, ProgramData>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct AdminOnly<'info> {
+    #[account(mut, seeds = [PROGRAM_CONFIG_SEED], bump = program_config.bump, has_one = admin @ GameError::NotAdmin)]
+    pub program_config: Account<'info, ProgramConfig>,
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetProtocolFee<'info> {
+    #[account(mut, seeds = [PROGRAM_CONFIG_SEED], bump = program_config.bump, has_one = admin @ GameError::NotAdmin)]
+    pub program_config: Account<'info, ProgramConfig>,
+    pub admin: Signer<'info>,
+    /// Required for a non-zero fee
+    #[account(seeds = [TREASURY_SEED], bump = treasury.bump)]
+    pub treasury: Option<Account<'info, Treasury>>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptAdmin<'info> {
+    #[account(mut, seeds = [PROGRAM_CONFIG_SEED], bump = program_config.bump)]
+    pub program_config: Account<'info, ProgramConfig>,
+    pub new_admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeTreasury<'info> {
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + Treasury::LEN,
+        seeds = [TREASURY_SEED],
+        bump
+    )]
+    pub treasury: Account<'info, Treasury>,
+    #[account(seeds = [PROGRAM_CONFIG_SEED], bump = program_config.bump, has_one = admin @ GameError::NotAdmin)]
+    pub program_config: Account<'info, ProgramConfig>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeGameCounter<'info> {
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + GameCounter::LEN,
+        seeds = [GAME_COUNTER_SEED],
+        bump
+    )]
+    pub game_counter: Account<'info, GameCounter>,
+    #[account(seeds = [PROGRAM_CONFIG_SEED], bump = program_config.bump, has_one = admin @ GameError::NotAdmin)]
+    pub program_config: Account<'info, ProgramConfig>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawTreasury<'info> {
+    #[account(mut, seeds = [TREASURY_SEED], bump = treasury.bump)]
+    pub treasury: Account<'info, Treasury>,
+    #[account(seeds = [PROGRAM_CONFIG_SEED], bump = program_config.bump, has_one = admin @ GameError::NotAdmin)]
+    pub program_config: Account<'info, ProgramConfig>,
+    pub admin: Signer<'info>,
+    /// CHECK: Any account can receive lamports
+    #[account(mut)]
+    pub recipient: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(season_id: u32)]
+pub struct CreateSeason<'info> {
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + Season::LEN,
+        seeds = [SEASON_SEED, &season_id.to_le_bytes()],
+        bump
+    )]
+    pub season: Account<'info, Season>,
+    #[account(mut, seeds = [PROGRAM_CONFIG_SEED], bump = program_config.bump, has_one = admin @ GameError::NotAdmin)]
+    pub program_config: Account<'info, ProgramConfig>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(season_id: u32)]
+pub struct CloseSeason<'info> {
+    #[account(mut, seeds = [SEASON_SEED, &season_id.to_le_bytes()], bump = season.bump)]
+    pub season: Account<'info, Season>,
+    #[account(mut, seeds = [TREASURY_SEED], bump = treasury.bump)]
+    pub treasury: Account<'info, Treasury>,
+    #[account(mut, seeds = [PROGRAM_CONFIG_SEED], bump = program_config.bump, has_one = admin @ GameError::NotAdmin)]
+    pub program_config: Account<'info, ProgramConfig>,
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(game_id: u64)]
+pub struct ForceRefund<'info> {
+    #[account(mut, seeds = [GAME_SEED, &game_id.to_le_bytes()], bump)]
+    pub game: Account<'info, Game>,
+    #[account(seeds = [PROGRAM_CONFIG_SEED], bump = program_config.bump, has_one = admin @ GameError::NotAdmin)]
+    pub program_config: Account<'info, ProgramConfig>,
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(version: u32)]
+pub struct CreateGameConfig<'info> {
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + GameConfig::LEN,
+        seeds = [GAME_CONFIG_SEED, &version.to_le_bytes()],
+        bump
+    )]
+    pub game_config: Account<'info, GameConfig>,
+    #[account(seeds = [PROGRAM_CONFIG_SEED], bump = program_config.bump, has_one = admin @ GameError::NotAdmin)]
+    pub program_config: Account<'info, ProgramConfig>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(version: u32)]
+pub struct RetireGameConfig<'info> {
+    #[account(mut, seeds = [GAME_CONFIG_SEED, &version.to_le_bytes()], bump = game_config.bump)]
+    pub game_config: Account<'info, GameConfig>,
+    #[account(seeds = [PROGRAM_CONFIG_SEED], bump = program_config.bump, has_one = admin @ GameError::NotAdmin)]
+    pub program_config: Account<'info, ProgramConfig>,
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(game_id: u64)]
+pub struct CreateActionLog<'info> {
+    #[account(mut, seeds = [GAME_SEED, &game_id.to_le_bytes()], bump)]
+    pub game: Account<'info, Game>,
+    #[account(
+        init,
+        payer = creator,
+        space = 8 + ActionLog::LEN,
+        seeds = [ACTION_LOG_SEED, &game_id.to_le_bytes()],
+        bump
+    )]
+    pub action_log: AccountLoader<'info, ActionLog>,
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(game_id: u64)]
+pub struct DelegateGame<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    /// Written manually: ownership moves to the delegation program during the instruction
+    #[account(seeds = [GAME_SEED, &game_id.to_le_bytes()], bump = game.bump, constraint = game.to_account_info().is_writable)]
+    pub game: Account<'info, Game>,
+    /// CHECK: Delegation program
+    #[account(address = DELEGATION_PROGRAM_ID)]
+    pub delegation_program: AccountInfo<'info>,
+    /// CHECK: Buffer, checked against its seeds before use
+    #[account(mut)]
+    pub buffer: AccountInfo<'info>,
+    /// CHECK: Delegation record, checked against its seeds before use
+    #[account(mut)]
+    pub delegation_record: AccountInfo<'info>,
+    /// CHECK: Delegation metadata, checked against its seeds before use
+    #[account(mut)]
+    pub delegation_metadata: AccountInfo<'info>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: Owner program
+    #[account(address = crate::ID)]
+    pub owner_program: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(game_id: u64)]
+pub struct CommitGame<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    #[account(mut, seeds = [GAME_SEED, &game_id.to_le_bytes()], bump)]
+    pub game: Account<'info, Game>,
+    /// CHECK: MagicBlock magic context
+    #[account(mut, address = MAGIC_CONTEXT_ID)]
+    pub magic_context: AccountInfo<'info>,
+    /// CHECK: MagicBlock magic program
+    #[account(address = MAGIC_PROGRAM_ID)]
+    pub magic_program: AccountInfo<'info>,
+    /// Replay log, required once the game has one
+    #[account(mut, seeds = [ACTION_LOG_SEED, &game_id.to_le_bytes()], bump)]
+    pub action_log: Option<AccountLoader<'info, ActionLog>>,
+}
+
+#[derive(Accounts)]
+pub struct ProcessUndelegation<'info> {
+    /// CHECK: Delegated account being restored, checked against the seeds
+    #[account(mut)]
+    pub base_account: AccountInfo<'info>,
+    /// CHECK: Undelegation buffer, signed by the delegation program
+    pub buffer: AccountInfo<'info>,
+    /// CHECK: Pays for the re-created account
+    #[account(mut)]
+    pub payer: AccountInfo<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+// ==================== STATE ====================
+
+#[account]
+pub struct Game {
+    pub game_id: u64,
+    pub creator: Pubkey,
+    pub stake_amount: u64,
+    pub player_count: u8,
+    pub status: GameStatus,
+    pub turn: u64,
+    pub winner: Option<Pubkey>,
+    pub grid: [[TileState; 8]; 8],
+    pub created_at: i64,
+    pub started_at: i64,
+    pub finished_at: i64,
+    /// Symmetric matrix of relations between player indices
+    pub diplomacy: [[DiplomacyState; MAX_PLAYERS as usize]; MAX_PLAYERS as usize],
+    /// Pending proposals, indexed [proposer][target]
+    pub treaty_proposals: [[Option<DiplomacyState>; MAX_PLAYERS as usize]; MAX_PLAYERS as usize],
+    /// Attacks between the pair are refused until this timestamp after a treaty is broken
+    pub truce_until: [[i64; MAX_PLAYERS as usize]; MAX_PLAYERS as usize],
+    pub team_layout: TeamLayout,
+    /// Wallet of each player index
+    pub players: [Pubkey; MAX_PLAYERS as usize],
+    /// Team of each player index
+    pub teams: [u8; MAX_PLAYERS as usize],
+    pub winning_team: Option<u8>,
+    pub victory_condition: VictoryCondition,
+    /// Team currently meeting the Domination / King of the Hill hold requirement
+    pub control_team: Option<u8>,
+    /// Turn at which `control_team` took control
+    pub control_since: u64,
+    pub game_mode: GameMode,
+    /// Number of outer rings collapsed by the storm
+    pub storm_ring: u8,
+    pub next_storm_slot: u64,
+    /// Capture the flag: one flag per player index
+    pub flags: [Flag; MAX_PLAYERS as usize],
+    /// Capture the flag: captures scored per player index
+    pub captures: [u8; MAX_PLAYERS as usize],
+    pub next_wave_slot: u64,
+    pub barbarian_waves: u16,
+    /// Whether the game is currently running on the ER
+    pub delegated: bool,
+    /// How often the ER commits state back to the base layer
+    pub commit_frequency_ms: u32,
+    pub bump: u8,
+    /// Whether gameplay instructions must append to the game's `ActionLog`
+    pub action_log: bool,
+    /// `GameConfig` version the balance was snapshotted from, 0 for the stock balance
+    pub config_version: u32,
+    pub balance: Balance,
+    /// Ended by the admin's `force_refund`; every player can claim their stake back
+    pub refunded: bool,
+    /// Protocol fee rate snapshotted from the program config at creation
+    pub fee_bps: u16,
+    /// Protocol fee taken from the pot at settlement
+    pub fee: u64,
+    /// Whether `fee` has been moved to the treasury
+    pub fee_collected: bool,
+    /// Whether `settle_ratings` has applied this game to the players' profiles
+    pub ratings_settled: bool,
+    /// Session tokens and agent authorities delegated to the ER with the game
+    pub delegated_sessions: u8,
+    /// Queue that formed the game; such games are closed to `join_game`
+    pub queue_id: Option<u32>,
+}
+
+impl Game {
+    pub const LEN: usize = 8 + 32 + 8 + 1 + 1 + 8 + (1 + 32) + (64 * 36) + 8 + 8 + 8
+        + 16 + (16 * 2) + (16 * 8)
+        + 1 + (4 * 32) + 4 + (1 + 1)
+        + VictoryCondition::LEN + (1 + 1) + 8
+        + GameMode::LEN + 1 + 8
+        + (4 * Flag::LEN) + 4
+        + 8 + 2
+        + 1 + 4
+        + 1
+        + 1
+        + 4 + Balance::LEN
+        + 1
+        + 2 + 8 + 1
+        + 1
+        + 1
+        + (1 + 4)
+        + 256;
+
+    pub fn diplomacy_between(&self, a: u8, b: u8) -> DiplomacyState {
+        self.diplomacy[a as usize][b as usize]
+    }
+
+    pub fn set_diplomacy(&mut self, a: u8, b: u8, state: DiplomacyState) {
+        let (a, b) = (a as usize, b as usize);
+        self.diplomacy[a][b] = state;
+        self.diplomacy[b][a] = state;
+        self.treaty_proposals[a][b] = None;
+        self.treaty_proposals[b][a] = None;
+    }
+
+    pub fn are_teammates(&self, a: u8, b: u8) -> bool {
+        self.teams[a as usize] == self.teams[b as usize]
+    }
+
+    pub fn team_size(&self, team: u8) -> u64 {
+        (0..self.player_count).filter(|&i| self.teams[i as usize] == team).count() as u64
+    }
+
+    pub fn finish(&mut self, winning_team: Option<u8>, now: i64) {
+        self.status = GameStatus::Finished;
+        self.finished_at = now;
+        self.fee = if self.refunded {
+            0
+        } else {
+            (self.pot() as u128 * self.fee_bps as u128 / BPS_DENOMINATOR as u128) as u64
+        };
+        self.winning_team = winning_team;
+        self.winner = winning_team.and_then(|team| {
+            (0..self.player_count)
+                .find(|&i| self.teams[i as usize] == team)
+                .map(|i| self.players[i as usize])
+        });
+        emit!(GameFinished {
+            game_id: self.game_id,
+            winning_team,
+            winner: self.winner,
+            pot: self.pot(),
+            fee: self.fee,
+            turn: self.turn,
+            finished_at: now,
+        });
+    }
+
+    /// Reset a freshly created game to an empty Standard / Conquest free-for-all lobby
+    pub fn init(&mut self, game_id: u64, bump: u8, creator: Pubkey, stake_amount: u64, now: i64) {
+        self.game_id = game_id;
+        self.bump = bump;
+        self.creator = creator;
+        self.stake_amount = stake_amount;
+        self.player_count = 0;
+        self.status = GameStatus::Lobby;
+        self.turn = 0;
+        self.winner = None;
+        self.team_layout = TeamLayout::FreeForAll;
+        self.players = [Pubkey::default(); MAX_PLAYERS as usize];
+        self.teams = [0; MAX_PLAYERS as usize];
+        self.winning_team = None;
+        self.victory_condition = VictoryCondition::Conquest;
+        self.control_team = None;
+        self.control_since = 0;
+        self.game_mode = GameMode::Standard;
+        self.storm_ring = 0;
+        self.next_storm_slot = 0;
+        self.flags = [Flag::default(); MAX_PLAYERS as usize];
+        self.captures = [0; MAX_PLAYERS as usize];
+        self.created_at = now;
+        self.started_at = 0;
+        self.finished_at = 0;
+        self.action_log = false;
+        self.refunded = false;
+        self.fee_bps = 0;
+        self.fee = 0;
+        self.fee_collected = false;
+        self.ratings_settled = false;
+        self.queue_id = None;
+        self.config_version = 0;
+        self.balance = Balance::default();
+
+        // Init empty grid
+        self.grid = [[TileState::Empty; 8]; 8];
+
+        // Everyone starts at war
+        self.diplomacy = [[DiplomacyState::War; MAX_PLAYERS as usize]; MAX_PLAYERS as usize];
+        self.treaty_proposals = [[None; MAX_PLAYERS as usize]; MAX_PLAYERS as usize];
+        self.truce_until = [[0; MAX_PLAYERS as usize]; MAX_PLAYERS as usize];
+    }
+
+    /// Seat `player` in the next slot and spawn their starting corner (2x2).
+    /// The stake is escrowed by the caller.
+    pub fn seat(&mut self, player_state: &mut PlayerState, player: Pubkey, bump: u8, now: i64) -> Result<()> {
+        require!(self.status == GameStatus::Lobby, GameError::GameNotInLobby);
+        require!(self.player_count < MAX_PLAYERS, GameError::GameFull);
+
+        let player_index = self.player_count;
+        let team = self.team_layout.team_of(player_index);
+        let mut state = self.engine_state([], now);
+        state
+            .join(team, self.team_layout.spawn_corner(player_index))
+            .map_err(GameError::from)?;
+
+        player_state.game_id = self.game_id;
+        player_state.player = player;
+        player_state.player_index = player_index;
+        player_state.bump = bump;
+        player_state.has_claimed = false;
+        player_state.strategy_mode = StrategyMode::Balanced;
+        player_state.strategy_profile = None;
+        player_state.autoplay = false;
+        player_state.last_autoplay_slot = 0;
+        player_state.autoplay_ticks = 0;
+
+        self.store(&state, [&mut *player_state]);
+        self.players[player_index as usize] = player;
+
+        emit!(PlayerJoined { game_id: self.game_id, player, player_index, team });
+        msg!("Player {} joined game {} as P{} (team {})", player, self.game_id, player_index, team);
+        Ok(())
+    }
+
+    /// Engine view of the game with the given players filled in; others keep their defaults
+    pub fn engine_state<'a>(&self, players: impl IntoIterator<Item = &'a PlayerState>, now: i64) -> engine::GameState {
+        let mut state = engine::GameState {
+            status: self.status.into(),
+            player_count: self.player_count,
+            turn: self.turn,
+            now,
+            grid: self.grid.map(|row| row.map(Into::into)),
+            teams: self.teams,
+            diplomacy: self.diplomacy.map(|row| row.map(Into::into)),
+            truce_until: self.truce_until,
+            victory_condition: self.victory_condition.into(),
+            control_team: self.control_team,
+            control_since: self.control_since,
+            game_mode: self.game_mode.into(),
+            storm_ring: self.storm_ring,
+            flags: self.flags.map(Into::into),
+            captures: self.captures,
+            barbarian_waves: self.barbarian_waves,
+            balance: self.balance.into(),
+            players: [engine::Player::default(); MAX_PLAYERS as usize],
+        };
+        for ps in players {
+            state.players[ps.player_index as usize] = engine::Player {
+                team: ps.team,
+                gold: ps.gold,
+                wood: ps.wood,
+                units: ps.units,
+                score: ps.score,
+                is_alive: ps.is_alive,
+            };
+        }
+        state
+    }
+
+    /// Write the engine's state back into the game and the given players
+    pub fn store<'a>(&mut self, state: &engine::GameState, players: impl IntoIterator<Item = &'a mut PlayerState>) {
+        self.status = state.status.into();
+        self.player_count = state.player_count;
+        self.turn = state.turn;
+        self.grid = state.grid.map(|row| row.map(Into::into));
+        self.teams = state.teams;
+        self.diplomacy = state.diplomacy.map(|row| row.map(Into::into));
+        self.truce_until = state.truce_until;
+        self.control_team = state.control_team;
+        self.control_since = state.control_since;
+        self.storm_ring = state.storm_ring;
+        self.flags = state.flags.map(Into::into);
+        self.captures = state.captures;
+        self.barbarian_waves = state.barbarian_waves;
+        for ps in players {
+            let player = &state.players[ps.player_index as usize];
+            ps.team = player.team;
+            ps.gold = player.gold;
+            ps.wood = player.wood;
+            ps.units = player.units;
+            ps.score = player.score;
+            ps.is_alive = player.is_alive;
+        }
+    }
+
+    pub fn pot(&self) -> u64 {
+        self.stake_amount * self.player_count as u64
+    }
+
+    /// Lamports owed to each member of `team` once the game is finished
+    /// Winning team splits the pot after the protocol fee; draws split it evenly and refunds return the stake
+    pub fn payout_for(&self, team: u8) -> u64 {
+        let prize = self.pot() - self.fee;
+        match self.winning_team {
+            Some(winning_team) if winning_team == team => prize / self.team_size(team),
+            Some(_) => 0,
+            None if self.refunded => self.stake_amount,
+            None => prize / self.player_count as u64,
+        }
+    }
+}
+
+/// Cross-game identity of a wallet, updated by `settle_ratings`
+#[account]
+pub struct PlayerProfile {
+    pub player: Pubkey,
+    /// Multiplayer ELO rating
+    pub rating: u32,
+    pub games_played: u32,
+    pub wins: u32,
+    pub losses: u32,
+    pub draws: u32,
+    pub total_score: u64,
+    pub last_game_id: Option<u64>,
+    /// Game most recently created through create_next_game with this profile passed
+    pub last_created_game_id: Option<u64>,
+    /// Season the season_* counters belong to; they restart when a new season is rated
+    pub season_id: u32,
+    pub season_points: u32,
+    pub season_wins: u32,
+    pub bump: u8,
+}
+
+impl PlayerProfile {
+    pub const LEN: usize = 32 + 4 + 4 + 4 + 4 + 4 + 8 + (1 + 8) + (1 + 8) + 4 + 4 + 4 + 1;
+}
+
+/// Hands out sequential game IDs so clients cannot collide with or squat on each other's
+#[account]
+pub struct GameCounter {
+    /// ID the next create_next_game or form_match will use
+    pub next_id: u64,
+    pub bump: u8,
+}
+
+impl GameCounter {
+    pub const LEN: usize = 8 + 1;
+
+    /// Take the next ID and advance the counter
+    pub fn assign(&mut self) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+}
+
+/// Players waiting for a game of one mode and stake tier; holds their stakes in escrow
+#[account]
+pub struct MatchQueue {
+    pub queue_id: u32,
+    pub stake_amount: u64,
+    pub team_layout: TeamLayout,
+    pub victory_condition: VictoryCondition,
+    pub game_mode: GameMode,
+    pub players_per_match: u8,
+    /// Largest rating spread allowed within one match
+    pub rating_band: u32,
+    pub config_version: u32,
+    pub balance: Balance,
+    pub count: u8,
+    /// Oldest first; only the first `count` are used
+    pub entries: [QueueEntry; QUEUE_CAPACITY],
+    pub bump: u8,
+}
+
+impl MatchQueue {
+    pub const LEN: usize = 4 + 8 + 1 + VictoryCondition::LEN + GameMode::LEN + 1 + 4 + 4 + Balance::LEN + 1
+        + (QUEUE_CAPACITY * QueueEntry::LEN)
+        + 1;
+
+    pub fn position(&self, player: &Pubkey) -> Option<usize> {
+        self.entries[..self.count as usize].iter().position(|e| e.player == *player)
+    }
+
+    /// Drop entry `index`, keeping the rest in arrival order
+    pub fn remove(&mut self, index: usize) {
+        let count = self.count as usize;
+        self.entries.copy_within(index + 1..count, index);
+        self.entries[count - 1] = QueueEntry::default();
+        self.count -= 1;
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct QueueEntry {
+    pub player: Pubkey,
+    /// Profile rating when queued
+    pub rating: u32,
+    pub enqueued_at: i64,
+}
+
+impl QueueEntry {
+    pub const LEN: usize = 32 + 4 + 8;
+}
+
+/// Time-boxed competition with a bounded leaderboard kept sorted by `settle_ratings`
+#[account]
+pub struct Season {
+    pub season_id: u32,
+    pub start_at: i64,
+    pub end_at: i64,
+    /// Lamports of the treasury paid out to the top ranks on close
+    pub prize_pool: u64,
+    /// Closed seasons are frozen
+    pub closed: bool,
+    pub entry_count: u8,
+    /// Best first; only the first `entry_count` are used
+    pub entries: [LeaderboardEntry; SEASON_BOARD_SIZE],
+    pub bump: u8,
+}
+
+impl Season {
+    pub const LEN: usize = 4 + 8 + 8 + 8 + 1 + 1 + (SEASON_BOARD_SIZE * LeaderboardEntry::LEN) + 1;
+
+    pub fn is_open(&self, at: i64) -> bool {
+        !self.closed && (self.start_at..self.end_at).contains(&at)
+    }
+
+    /// Insert or refresh `profile` on the board, keeping it sorted and bounded
+    pub fn record(&mut self, profile: &PlayerProfile) {
+        let entry = LeaderboardEntry {
+            player: profile.player,
+            points: profile.season_points,
+            wins: profile.season_wins,
+            rating: profile.rating,
+        };
+        let count = self.entry_count as usize;
+        match self.entries[..count].iter().position(|e| e.player == entry.player) {
+            Some(i) => self.entries[i] = entry,
+            None if count < SEASON_BOARD_SIZE => {
+                self.entries[count] = entry;
+                self.entry_count += 1;
+            }
+            None if entry.rank_key() > self.entries[count - 1].rank_key() => self.entries[count - 1] = entry,
+            None => return,
+        }
+        self.entries[..self.entry_count as usize].sort_by_key(|e| std::cmp::Reverse(e.rank_key()));
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct LeaderboardEntry {
+    pub player: Pubkey,
+    pub points: u32,
+    pub wins: u32,
+    /// Rating when last recorded, breaks ties on points
+    pub rating: u32,
+}
+
+impl LeaderboardEntry {
+    pub const LEN: usize = 32 + 4 + 4 + 4;
+
+    pub fn rank_key(&self) -> (u32, u32, u32) {
+        (self.points, self.wins, self.rating)
+    }
+}
+
+#[account]
+pub struct PlayerState {
+    pub game_id: u64,
+    pub player: Pubkey,
+    pub player_index: u8,
+    pub gold: u64,
+    pub wood: u64,
+    pub units: u8,
+    pub score: u64,
+    pub is_alive: bool,
+    pub strategy_mode: StrategyMode,
+    pub team: u8,
+    pub has_claimed: bool,
+    pub bump: u8,
+    /// Opted in to `autoplay_tick`
+    pub autoplay: bool,
+    pub last_autoplay_slot: u64,
+    pub autoplay_ticks: u64,
+    /// Custom weights overriding the `strategy_mode` preset
+    pub strategy_profile: Option<StrategyProfile>,
+}
+
+impl PlayerState {
+    pub const LEN: usize =
+        8 + 32 + 1 + 8 + 8 + 1 + 8 + 1 + 1 + 1 + 1 + 1 + 1 + 8 + 8 + (1 + StrategyProfile::LEN) + 64;
+
+    /// Profile the autoplay policy and agents act on
+    pub fn profile(&self) -> StrategyProfile {
+        self.strategy_profile.unwrap_or_else(|| self.strategy_mode.profile())
+    }
+
+    pub fn resource(&mut self, resource_type: ResourceType) -> &mut u64 {
+        match resource_type {
+            ResourceType::Gold => &mut self.gold,
+            ResourceType::Wood => &mut self.wood,
+        }
+    }
+}
+
+#[account]
+pub struct TradeOffer {
+    pub game_id: u64,
+    pub offer_id: u64,
+    pub maker: Pubkey,
+    pub target: Option<Pubkey>,
+    pub offer_resource: ResourceType,
+    pub offer_amount: u64,
+    pub request_resource: ResourceType,
+    pub request_amount: u64,
+    pub created_at: i64,
+    pub expires_at: i64,
+}
+
+impl TradeOffer {
+    pub const LEN: usize = 8 + 8 + 32 + (1 + 32) + 1 + 8 + 1 + 8 + 8 + 8;
+}
+
+#[account]
+pub struct SessionToken {
+    pub game_id: u64,
+    /// Player wallet the session acts for
+    pub authority: Pubkey,
+    pub session_key: Pubkey,
+    pub valid_until: i64,
+    /// Bitmask of PERMISSION_* gameplay actions; 0 once revoked
+    pub permissions: u16,
+    pub max_gold_spend: u64,
+    pub max_wood_spend: u64,
+    pub gold_spent: u64,
+    pub wood_spent: u64,
+    pub bump: u8,
+}
+
+impl SessionToken {
+    pub const LEN: usize = 8 + 32 + 32 + 8 + 2 + 8 + 8 + 8 + 8 + 1;
+
+    /// Count `amount` against the session's spend limit for `resource_type`
+    pub fn charge(&mut self, resource_type: ResourceType, amount: u64) -> Result<()> {
+        let (spent, limit) = match resource_type {
+            ResourceType::Gold => (&mut self.gold_spent, self.max_gold_spend),
+            ResourceType::Wood => (&mut self.wood_spent, self.max_wood_spend),
+        };
+        require!(*spent + amount <= limit, GameError::SpendLimitExceeded);
+        *spent += amount;
+        Ok(())
+    }
+}
+
+#[account]
+pub struct AgentAuthority {
+    pub game_id: u64,
+    /// Player wallet the agent acts for
+    pub player: Pubkey,
+    pub agent: Pubkey,
+    /// Bitmask of PERMISSION_* actions, limited to PERMISSION_AGENT_ALLOWED; 0 once revoked
+    pub permissions: u16,
+    /// 0 disables the rate limit
+    pub max_actions_per_turn: u16,
+    /// Length of an agent turn in slots
+    pub turn_slots: u64,
+    pub current_turn: u64,
+    pub actions_this_turn: u16,
+    pub bump: u8,
+}
+
+impl AgentAuthority {
+    pub const LEN: usize = 8 + 32 + 32 + 2 + 2 + 8 + 8 + 2 + 1;
+
+    /// Count one action against the per-turn rate limit
+    pub fn record_action(&mut self, slot: u64) -> Result<()> {
+        if self.max_actions_per_turn == 0 {
+            return Ok(());
+        }
+        let turn = slot / self.turn_slots;
+        if turn != self.current_turn {
+            self.current_turn = turn;
+            self.actions_this_turn = 0;
+        }
+        require!(self.actions_this_turn < self.max_actions_per_turn, GameError::RateLimited);
+        self.actions_this_turn += 1;
+        Ok(())
+    }
+}
+
+/// Program-wide admin settings (singleton)
+#[account]
+pub struct ProgramConfig {
+    pub admin: Pubkey,
+    /// Set by `transfer_admin`, becomes admin on `accept_admin`
+    pub pending_admin: Option<Pubkey>,
+    /// Blocks `create_game` and `join_game`
+    pub paused: bool,
+    /// Protocol fee on staked games, capped by MAX_PROTOCOL_FEE_BPS
+    pub fee_bps: u16,
+    /// Latest season from `create_season`, cleared when it closes.
+    /// Games finishing inside its window must be settled with it.
+    pub current_season: Option<u32>,
+    pub season_start_at: i64,
+    /// Seasons never overlap: the next one starts at or after this
+    pub season_end_at: i64,
+    pub bump: u8,
+}
+
+impl ProgramConfig {
+    pub const LEN: usize = 32 + (1 + 32) + 1 + 2 + (1 + 4) + 8 + 8 + 1;
+}
+
+/// Holds protocol fees until the admin withdraws them
+#[account]
+pub struct Treasury {
+    pub total_collected: u64,
+    pub total_withdrawn: u64,
+    pub bump: u8,
+}
+
+impl Treasury {
+    pub const LEN: usize = 8 + 8 + 1;
+}
+
+/// Versioned balance preset, snapshotted into each game created with it
+#[account]
+pub struct GameConfig {
+    pub version: u32,
+    /// Program admin that published the preset
+    pub admin: Pubkey,
+    pub balance: Balance,
+    /// Retired presets can no longer be chosen for new games
+    pub retired: bool,
+    pub created_at: i64,
+    pub bump: u8,
+}
+
+impl GameConfig {
+    pub const LEN: usize = 4 + 32 + Balance::LEN + 1 + 8 + 1;
+}
+
+/// Economy a game is played with
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Balance {
+    pub initial_gold: u64,
+    pub initial_wood: u64,
+    pub unit_cost_gold: u64,
+    pub defense_cost_wood: u64,
+    pub resource_per_tick: u64,
+    pub max_units: u8,
+}
+
+impl Balance {
+    pub const LEN: usize = 8 * 5 + 1;
+}
+
+impl Default for Balance {
+    fn default() -> Self {
+        engine::Balance::default().into()
+    }
+}
+
+#[account]
+pub struct Market {
+    pub game_id: u64,
+    pub gold_reserve: u64,
+    pub wood_reserve: u64,
+    pub fee_bps: u16,
+    /// Wood paid per gold at the current reserves, scaled by PRICE_SCALE
+    pub gold_price: u64,
+    /// Gold paid per wood at the current reserves, scaled by PRICE_SCALE
+    pub wood_price: u64,
+    pub last_trade_at: i64,
+}
+
+impl Market {
+    pub const LEN: usize = 8 + 8 + 8 + 2 + 8 + 8 + 8;
+
+    pub fn reserve(&mut self, resource_type: ResourceType) -> &mut u64 {
+        match resource_type {
+            ResourceType::Gold => &mut self.gold_reserve,
+            ResourceType::Wood => &mut self.wood_reserve,
+        }
+    }
+
+    /// Amount received for `amount_in`, after fee, under x * y = k
+    pub fn quote(&self, resource_in: ResourceType, amount_in: u64) -> u64 {
+        let (reserve_in, reserve_out) = match resource_in {
+            ResourceType::Gold => (self.gold_reserve, self.wood_reserve),
+            ResourceType::Wood => (self.wood_reserve, self.gold_reserve),
+        };
+        let in_after_fee = amount_in as u128 * (BPS_DENOMINATOR - self.fee_bps as u64) as u128
+            / BPS_DENOMINATOR as u128;
+        (reserve_out as u128 * in_after_fee / (reserve_in as u128 + in_after_fee)) as u64
+    }
+
+    pub fn update_prices(&mut self) {
+        self.gold_price = (self.wood_reserve as u128 * PRICE_SCALE as u128
+            / self.gold_reserve.max(1) as u128) as u64;
+        self.wood_price = (self.gold_reserve as u128 * PRICE_SCALE as u128
+            / self.wood_reserve.max(1) as u128) as u64;
+    }
+}
+
+/// Check that `signer` may act for `player_state`: the wallet itself, a registered agent or
+/// a live session key holding `permission`. `spend` is charged against the session's limits.
+pub fn authorize(
+    player_state: &PlayerState,
+    signer: &Pubkey,
+    session: Option<&mut SessionToken>,
+    agent: Option<&mut AgentAuthority>,
+    permission: u16,
+    spend: Option<(ResourceType, u64)>,
+) -> Result<()> {
+    if *signer == player_state.player {
+        return Ok(());
+    }
+
+    if let Some(agent) = agent {
+        require!(
+            agent.player == player_state.player
+                && agent.agent == *signer
+                && agent.game_id == player_state.game_id,
+            GameError::Unauthorized
+        );
+        require!(agent.permissions != 0, GameError::Revoked);
+        require!(agent.permissions & permission == permission, GameError::NotPermitted);
+        return agent.record_action(Clock::get()?.slot);
+    }
+
+    let session = session.ok_or(GameError::Unauthorized)?;
+    require!(
+        session.authority == player_state.player
+            && session.session_key == *signer
+            && session.game_id == player_state.game_id,
+        GameError::Unauthorized
+    );
+    require!(session.permissions != 0, GameError::Revoked);
+    require!(Clock::get()?.unix_timestamp < session.valid_until, GameError::SessionExpired);
+    require!(session.permissions & permission == permission, GameError::NotPermitted);
+    if let Some((resource_type, amount)) = spend {
+        session.charge(resource_type, amount)?;
+    }
+    Ok(())
+}
+
+/// Shared setup for create_game and create_next_game once the ID is known
+#[allow(clippy::too_many_arguments)]
+pub fn open_game(
+    game: &mut Game,
+    game_id: u64,
+    bump: u8,
+    creator: Pubkey,
+    stake_amount: u64,
+    (team_layout, victory_condition, game_mode): (TeamLayout, VictoryCondition, GameMode),
+    program_config: &ProgramConfig,
+    game_config: &Option<Account<GameConfig>>,
+) -> Result<()> {
+    require!(!program_config.paused, GameError::ProgramPaused);
+    let now = Clock::get()?.unix_timestamp;
+    validate_rules(victory_condition, game_mode, now)?;
+
+    // Snapshot the chosen balance preset so later preset changes never reach this game
+    let (config_version, balance) = balance_from(game_config)?;
+
+    game.init(game_id, bump, creator, stake_amount, now);
+    game.team_layout = team_layout;
+    game.victory_condition = victory_condition;
+    game.game_mode = game_mode;
+    game.fee_bps = program_config.fee_bps;
+    game.config_version = config_version;
+    game.balance = balance;
+
+    emit!(GameCreated {
+        game_id,
+        creator,
+        stake_amount,
+        team_layout,
+        victory_condition,
+        game_mode,
+        config_version,
+        created_at: game.created_at,
+    });
+    msg!(
+        "Game {} created by {} ({:?}, {:?}, config v{})",
+        game_id,
+        creator,
+        team_layout,
+        game_mode,
+        config_version
+    );
+    Ok(())
+}
+
+/// Check the rules a new game is created with; a Timed deadline must still be ahead at `now`
+pub fn validate_rules(victory_condition: VictoryCondition, game_mode: GameMode, now: i64) -> Result<()> {
+    require!(
+        engine::VictoryCondition::from(victory_condition).is_valid(),
+        GameError::InvalidVictoryCondition
+    );
+    if let VictoryCondition::Timed { deadline } = victory_condition {
+        require!(deadline > now, GameError::InvalidVictoryCondition);
+    }
+    require!(engine::GameMode::from(game_mode).is_valid(), GameError::InvalidGameMode);
+    Ok(())
+}
+
+/// Version and balance to snapshot from an optional preset, the stock balance without one
+pub fn balance_from(config: &Option<Account<GameConfig>>) -> Result<(u32, Balance)> {
+    match config {
+        Some(config) => {
+            require!(!config.retired, GameError::GameConfigRetired);
+            Ok((config.version, config.balance))
+        }
+        None => Ok((0, Balance::default())),
+    }
+}
+
+/// Create a program-owned PDA, like Anchor's `init`. An address that was already sent lamports
+/// cannot go through `create_account`, so it is topped up to rent exemption, allocated and assigned.
+pub fn create_pda_account<'info>(
+    system_program: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    account: &AccountInfo<'info>,
+    space: usize,
+    signer_seeds: &[&[u8]],
+) -> Result<()> {
+    use anchor_lang::system_program::{allocate, assign, create_account, transfer};
+    use anchor_lang::system_program::{Allocate, Assign, CreateAccount, Transfer};
+
+    let rent = Rent::get()?.minimum_balance(space);
+    let lamports = account.lamports();
+    if lamports == 0 {
+        return create_account(
+            CpiContext::new_with_signer(
+                system_program.clone(),
+                CreateAccount { from: payer.clone(), to: account.clone() },
+                &[signer_seeds],
+            ),
+            rent,
+            space as u64,
+            &crate::ID,
+        );
+    }
+
+    if lamports < rent {
+        transfer(
+            CpiContext::new(system_program.clone(), Transfer { from: payer.clone(), to: account.clone() }),
+            rent - lamports,
+        )?;
+    }
+    allocate(
+        CpiContext::new_with_signer(
+            system_program.clone(),
+            Allocate { account_to_allocate: account.clone() },
+            &[signer_seeds],
+        ),
+        space as u64,
+    )?;
+    assign(
+        CpiContext::new_with_signer(
+            system_program.clone(),
+            Assign { account_to_assign: account.clone() },
+            &[signer_seeds],
+        ),
+        &crate::ID,
+    )
+}
+
+/// Load and validate one PlayerState per joined player from `accounts`
+pub fn load_player_states<'info>(
+    game: &Game,
+    accounts: &'info [AccountInfo<'info>],
+) -> Result<Vec<Account<'info, PlayerState>>> {
+    require!(accounts.len() == game.player_count as usize, GameError::InvalidPlayerAccounts);
+
+    let mut players: Vec<Account<'info, PlayerState>> = Vec::with_capacity(accounts.len());
+    for info in accounts {
+        require!(info.is_writable, GameError::InvalidPlayerAccounts);
+        let ps = Account::<PlayerState>::try_from(info)?;
+        require!(
+            ps.game_id == game.game_id
+                && ps.player_index < game.player_count
+                && game.players[ps.player_index as usize] == ps.player
+                && players.iter().all(|p| p.player_index != ps.player_index),
+            GameError::InvalidPlayerAccounts
+        );
+        players.push(ps);
+    }
+    Ok(players)
+}
+
+/// Fixed-capacity ring buffer of every gameplay action, for replays
+#[account(zero_copy)]
+pub struct ActionLog {
+    pub game_id: u64,
+    /// Records ever appended; the oldest kept is `count - ACTION_LOG_CAPACITY`
+    pub count: u64,
+    pub records: [ActionRecord; ACTION_LOG_CAPACITY],
+}
+
+impl ActionLog {
+    pub const LEN: usize = 8 + 8 + ACTION_LOG_CAPACITY * ActionRecord::LEN;
+
+    pub fn push(&mut self, record: ActionRecord) {
+        self.records[(self.count % ACTION_LOG_CAPACITY as u64) as usize] = record;
+        self.count += 1;
+    }
+}
+
+/// Packed action record. Field meaning depends on `kind`:
+///
+/// - Move: (x, y) -> (to_x, to_y) with `count` units; `result` is the `MoveOutcome` code,
+///   `value` the units left on the tile (or resource amount claimed)
+/// - Build: (x, y)
+/// - Train: `count` units at (x, y); `value` gold spent
+/// - Collect: `value` gold, `extra` wood
+/// - Swap: `result` resource in; `value` amount in, `extra` amount out
+/// - Trade: `player` taker, `x` maker; `result` resource offered; `value` offered, `extra` requested
+/// - Treaty / BreakTreaty: `x` other player; `result` new `DiplomacyState`
+/// - Storm: `x` ring collapsed; `value` units destroyed
+/// - Barbarians: `player` target, (x, y) tile hit, `count` wave strength,
+///   `result` 1 if the tile fell, `value` its defense
+/// - OfferTrade: `player` maker, `x` target (255 = open); `result` resource offered (escrowed),
+///   `to_x` resource requested; `value` offered, `extra` requested
+/// - CancelOffer: `player` maker; `result` resource returned; `value` amount returned
+/// - ProposeTreaty: `x` target player; `result` proposed `DiplomacyState`
+/// - Autoplay: `result` 1 if enabled, 0 if disabled
+#[zero_copy]
+#[derive(Default, Debug, PartialEq, Eq)]
+pub struct ActionRecord {
+    pub turn: u32,
+    pub value: u32,
+    pub extra: u32,
+    pub player: u8,
+    pub kind: u8,
+    pub x: u8,
+    pub y: u8,
+    pub to_x: u8,
+    pub to_y: u8,
+    pub count: u8,
+    pub result: u8,
+}
+
+impl ActionRecord {
+    pub const LEN: usize = 4 + 4 + 4 + 8;
+
+    pub fn new(kind: ActionKind, turn: u64, player: u8) -> Self {
+        ActionRecord { turn: turn as u32, kind: kind as u8, player, ..Default::default() }
+    }
+}
+
+/// Saturate an amount into an action record field
+pub fn compact(amount: u64) -> u32 {
+    u32::try_from(amount).unwrap_or(u32::MAX)
+}
+
+/// Append to the game's action log; once a game has a log every gameplay instruction must pass it
+pub fn log_action(game: &Game, log: &Option<AccountLoader<ActionLog>>, record: ActionRecord) -> Result<()> {
+    match log {
+        Some(log) => log.load_mut()?.push(record),
+        None => require!(!game.action_log, GameError::ActionLogRequired),
+    }
+    Ok(())
+}
+
+/// Seeds and bump of a SessionToken or AgentAuthority belonging to game `game_id`
+pub fn session_seeds<'info>(info: &'info AccountInfo<'info>, game_id: u64) -> Result<(Vec<Vec<u8>>, u8)> {
+    let game_id_bytes = game_id.to_le_bytes().to_vec();
+    let (seeds, bump, owner_game) = if let Ok(session) = Account::<SessionToken>::try_from(info) {
+        let seeds = vec![
+            SESSION_SEED.to_vec(),
+            game_id_bytes,
+            session.authority.to_bytes().to_vec(),
+            session.session_key.to_bytes().to_vec(),
+        ];
+        (seeds, session.bump, session.game_id)
+    } else {
+        let agent = Account::<AgentAuthority>::try_from(info)?;
+        let seeds = vec![AGENT_SEED.to_vec(), game_id_bytes, agent.player.to_bytes().to_vec()];
+        (seeds, agent.bump, agent.game_id)
+    };
+    require!(owner_game == game_id, GameError::InvalidDelegationAccounts);
+
+    let mut signer_seeds: Vec<&[u8]> = seeds.iter().map(|seed| seed.as_slice()).collect();
+    let bump_seed = [bump];
+    signer_seeds.push(&bump_seed);
+    let expected = Pubkey::create_program_address(&signer_seeds, &crate::ID)
+        .map_err(|_| GameError::InvalidDelegationAccounts)?;
+    require_keys_eq!(info.key(), expected, GameError::InvalidDelegationAccounts);
+    Ok((seeds, bump))
+}
+
+/// Validate the player states and delegated session / agent accounts passed to commit or
+/// undelegate a game, and list every account to commit alongside it
+pub fn delegated_accounts<'info>(
+    game: &Game,
+    accounts: &'info [AccountInfo<'info>],
+    log: &Option<AccountLoader<'info, ActionLog>>,
+) -> Result<Vec<AccountInfo<'info>>> {
+    let player_count = (game.player_count as usize).min(accounts.len());
+    let (players, sessions) = accounts.split_at(player_count);
+    load_player_states(game, players)?;
+    require!(sessions.len() == game.delegated_sessions as usize, GameError::InvalidDelegationAccounts);
+    for info in sessions {
+        require!(info.is_writable, GameError::InvalidDelegationAccounts);
+        session_seeds(info, game.game_id)?;
+    }
+
+    let mut committed = with_action_log(players, log);
+    committed.extend_from_slice(sessions);
+    Ok(committed)
+}
+
+/// Player accounts plus the action log, if any, for committing from the ER
+pub fn with_action_log<'info>(
+    players: &[AccountInfo<'info>],
+    log: &Option<AccountLoader<'info, ActionLog>>,
+) -> Vec<AccountInfo<'info>> {
+    let mut accounts = players.to_vec();
+    if let Some(log) = log {
+        accounts.push(log.to_account_info());
+    }
+    accounts
+}
+
+/// Relative weights per kind of action plus how safe an attack must be
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct StrategyProfile {
+    pub expansion: u8,
+    pub aggression: u8,
+    pub defense: u8,
+    pub saving: u8,
+    /// Minimum attacker strength as a percentage of the defender's (100 = any winning attack)
+    pub risk_threshold: u16,
+}
+
+impl StrategyProfile {
+    pub const LEN: usize = 1 + 1 + 1 + 1 + 2;
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Flag {
+    pub home_x: u8,
+    pub home_y: u8,
+    pub x: u8,
+    pub y: u8,
+    /// Player index whose stack on (x, y) is carrying the flag
+    pub carrier: Option<u8>,
+}
+
+impl Flag {
+    pub const LEN: usize = 1 + 1 + 1 + 1 + (1 + 1);
+}
+
+// ==================== RATINGS ====================
+
+/// Load the PlayerProfile of each of `players`, in the same order
+pub fn load_profiles<'info>(
+    players: &[Account<'info, PlayerState>],
+    accounts: &'info [AccountInfo<'info>],
+) -> Result<Vec<Account<'info, PlayerProfile>>> {
+    let mut profiles = Vec::with_capacity(accounts.len());
+    for (ps, info) in players.iter().zip(accounts) {
+        require!(info.is_writable, GameError::InvalidPlayerAccounts);
+        let profile = Account::<PlayerProfile>::try_from(info)?;
+        require!(profile.player == ps.player, GameError::InvalidPlayerAccounts);
+        profiles.push(profile);
+    }
+    Ok(profiles)
+}
+
+/// Final placement of each player, 0 = best. The winning team places first; everyone
+/// else is ranked by survival, then score. Teammates share their best member's placement.
+pub fn placements<P: std::ops::Deref<Target = PlayerState>>(game: &Game, players: &[P]) -> Vec<u8> {
+    let standing = |ps: &PlayerState| {
+        let team_best = players
+            .iter()
+            .filter(|other| other.team == ps.team)
+            .map(|other| (other.is_alive, other.score))
+            .max()
+            .unwrap_or_default();
+        (game.winning_team == Some(ps.team), team_best)
+    };
+    players
+        .iter()
+        .map(|ps| players.iter().filter(|other| standing(other) > standing(ps)).count() as u8)
+        .collect()
+}
+
+/// Multiplayer ELO: every player plays a virtual match against each opponent outside their
+/// team, scoring 1 for a better placement, 0.5 for the same and 0 for a worse one.
+/// K is split across the opponents so one game moves a rating by at most RATING_K.
+pub fn elo_deltas(ratings: &[u32], placements: &[u8], teams: &[u8]) -> Vec<i32> {
+    (0..ratings.len())
+        .map(|i| {
+            let opponents: Vec<usize> = (0..ratings.len()).filter(|&j| teams[j] != teams[i]).collect();
+            if opponents.is_empty() {
+                return 0;
+            }
+            let total: f64 = opponents
+                .iter()
+                .map(|&j| {
+                    let expected = 1.0 / (1.0 + 10f64.powf((ratings[j] as f64 - ratings[i] as f64) / 400.0));
+                    let actual = match placements[i].cmp(&placements[j]) {
+                        std::cmp::Ordering::Less => 1.0,
+                        std::cmp::Ordering::Equal => 0.5,
+                        std::cmp::Ordering::Greater => 0.0,
+                    };
+                    actual - expected
+                })
+                .sum();
+            (RATING_K * total / opponents.len() as f64).round() as i32
+        })
+        .collect()
+}
+
+// ==================== ENGINE ====================
+
+/// Apply `action` through the engine to the game and the given players, write the result
+/// back and report it. Returns the action's log record.
+pub fn run_action(
+    game: &mut Game,
+    players: &mut [&mut PlayerState],
+    action: engine::Action,
+    now: i64,
+) -> Result<ActionRecord> {
+    let mut state = game.engine_state(players.iter().map(|ps| &**ps), now);
+    let outcome = state.apply(action).map_err(GameError::from)?;
+    game.store(&state, players.iter_mut().map(|ps| &mut **ps));
+    Ok(report(game, action, outcome))
+}
+
+/// Emit events and logs for an applied action and build its log record
+pub fn report(game: &Game, action: engine::Action, outcome: engine::Outcome) -> ActionRecord {
+    let game_id = game.game_id;
+    match (action, outcome) {
+        (engine::Action::Move { player, from, to, count }, engine::Outcome::Moved { outcome, flag_captured }) => {
+            emit!(UnitsMoved {
+                game_id,
+                player_index: player,
+                from_x: from.0,
+                from_y: from.1,
+                to_x: to.0,
+                to_y: to.1,
+                unit_count: count,
+                outcome: outcome.into(),
+                turn: game.turn,
+            });
+            if outcome.captures_tile() {
+                emit!(TileCaptured {
+                    game_id,
+                    player_index: player,
+                    x: to.0,
+                    y: to.1,
+                    previous_owner: match outcome {
+                        engine::MoveOutcome::Won { defender, .. } => Some(defender),
+                        _ => None,
+                    },
+                });
+            }
+            if flag_captured {
+                msg!("P{} captured a flag!", player);
+            }
+            msg!("P{} moved {} units ({},{}) -> ({},{})", player, count, from.0, from.1, to.0, to.1);
+            let outcome = MoveOutcome::from(outcome);
+            ActionRecord {
+                x: from.0,
+                y: from.1,
+                to_x: to.0,
+                to_y: to.1,
+                count,
+                result: outcome.code(),
+                value: compact(outcome.value()),
+                ..ActionRecord::new(ActionKind::Move, game.turn, player)
+            }
+        }
+        (engine::Action::BuildDefense { player, x, y }, engine::Outcome::DefenseBuilt) => {
+            emit!(DefenseBuilt { game_id, player_index: player, x, y });
+            msg!("P{} built defense at ({},{})", player, x, y);
+            ActionRecord { x, y, ..ActionRecord::new(ActionKind::Build, game.turn, player) }
+        }
+        (engine::Action::TrainUnits { player, x, y, count }, engine::Outcome::UnitsTrained { gold_spent }) => {
+            emit!(UnitsTrained { game_id, player_index: player, x, y, count, gold_spent });
+            msg!("P{} trained {} units at ({},{})", player, count, x, y);
+            ActionRecord {
+                x,
+                y,
+                count,
+                value: compact(gold_spent),
+                ..ActionRecord::new(ActionKind::Train, game.turn, player)
+            }
+        }
+        (engine::Action::CollectResources { player }, engine::Outcome::Collected { gold, wood }) => {
+            emit!(ResourcesCollected { game_id, player_index: player, gold, wood });
+            msg!("P{} collected {} gold, {} wood", player, gold, wood);
+            ActionRecord {
+                value: compact(gold),
+                extra: compact(wood),
+                ..ActionRecord::new(ActionKind::Collect, game.turn, player)
+            }
+        }
+        (engine::Action::AdvanceStorm, engine::Outcome::StormAdvanced { ring, units_destroyed, eliminated }) => {
+            report_eliminations(game, eliminated);
+            msg!("Game {} storm collapsed ring {}", game_id, ring);
+            ActionRecord {
+                x: ring,
+                value: compact(units_destroyed),
+                ..ActionRecord::new(ActionKind::Storm, game.turn, NO_PLAYER)
+            }
+        }
+        (
+            engine::Action::BarbarianWave,
+            engine::Outcome::BarbariansAttacked { target, x, y, strength, defense, overrun },
+        ) => {
+            msg!("Barbarians ({}) attacked P{} at ({},{})", strength, target, x, y);
+            ActionRecord {
+                x,
+                y,
+                count: strength,
+                result: overrun as u8,
+                value: defense as u32,
+                ..ActionRecord::new(ActionKind::Barbarians, game.turn, target)
+            }
+        }
+        (action, outcome) => unreachable!("engine answered {:?} with {:?}", action, outcome),
+    }
+}
+
+pub fn report_eliminations(game: &Game, eliminated: [bool; MAX_PLAYERS as usize]) {
+    for (player_index, _) in eliminated.iter().enumerate().filter(|(_, &out)| out) {
+        emit!(PlayerEliminated { game_id: game.game_id, player_index: player_index as u8 });
+        msg!("P{} eliminated", player_index);
+    }
+}
+
+/// Two-way conversion between an account enum and its engine twin with the same unit variants
+macro_rules! mirror_enum {
+    ($ty:ident { $($variant:ident),* $(,)? }) => {
+        impl From<$ty> for engine::$ty {
+            fn from(value: $ty) -> Self {
+                match value {
+                    $($ty::$variant => engine::$ty::$variant,)*
+                }
+            }
+        }
+
+        impl From<engine::$ty> for $ty {
+            fn from(value: engine::$ty) -> Self {
+                match value {
+                    $(engine::$ty::$variant => $ty::$variant,)*
+                }
+            }
+        }
+    };
+}
+
+mirror_enum!(GameStatus { Lobby, Active, Finished });
+mirror_enum!(ResourceType { Gold, Wood });
+mirror_enum!(DiplomacyState { War, Peace, Alliance });
+mirror_enum!(StrategyMode { Aggressive, Defensive, Balanced, Economic });
+
+impl From<TileState> for engine::TileState {
+    fn from(tile: TileState) -> Self {
+        match tile {
+            TileState::Empty => engine::TileState::Empty,
+            TileState::Owned { player, units, has_defense, has_mine } => {
+                engine::TileState::Owned { player, units, has_defense, has_mine }
+            }
+            TileState::Resource { resource_type, amount } => {
+                engine::TileState::Resource { resource_type: resource_type.into(), amount }
+            }
+            TileState::Collapsed => engine::TileState::Collapsed,
+            TileState::Neutral { units, reward } => engine::TileState::Neutral { units, reward: reward.into() },
+        }
+    }
+}
+
+impl From<engine::TileState> for TileState {
+    fn from(tile: engine::TileState) -> Self {
+        match tile {
+            engine::TileState::Empty => TileState::Empty,
+            engine::TileState::Owned { player, units, has_defense, has_mine } => {
+                TileState::Owned { player, units, has_defense, has_mine }
+            }
+            engine::TileState::Resource { resource_type, amount } => {
+                TileState::Resource { resource_type: resource_type.into(), amount }
+            }
+            engine::TileState::Collapsed => TileState::Collapsed,
+            engine::TileState::Neutral { units, reward } => TileState::Neutral { units, reward: reward.into() },
+        }
+    }
+}
+
+impl From<CampReward> for engine::CampReward {
+    fn from(reward: CampReward) -> Self {
+        match reward {
+            CampReward::Gold { amount } => engine::CampReward::Gold { amount },
+            CampReward::Wood { amount } => engine::CampReward::Wood { amount },
+            CampReward::Units { count } => engine::CampReward::Units { count },
+        }
+    }
+}
+
+impl From<engine::CampReward> for CampReward {
+    fn from(reward: engine::CampReward) -> Self {
+        match reward {
+            engine::CampReward::Gold { amount } => CampReward::Gold { amount },
+            engine::CampReward::Wood { amount } => CampReward::Wood { amount },
+            engine::CampReward::Units { count } => CampReward::Units { count },
+        }
+    }
+}
+
+impl From<Flag> for engine::Flag {
+    fn from(flag: Flag) -> Self {
+        engine::Flag { home_x: flag.home_x, home_y: flag.home_y, x: flag.x, y: flag.y, carrier: flag.carrier }
+    }
+}
+
+impl From<engine::Flag> for Flag {
+    fn from(flag: engine::Flag) -> Self {
+        Flag { home_x: flag.home_x, home_y: flag.home_y, x: flag.x, y: flag.y, carrier: flag.carrier }
+    }
+}
+
+impl From<StrategyProfile> for engine::StrategyProfile {
+    fn from(p: StrategyProfile) -> Self {
+        engine::StrategyProfile {
+            expansion: p.expansion,
+            aggression: p.aggression,
+            defense: p.defense,
+            saving: p.saving,
+            risk_threshold: p.risk_threshold,
+        }
+    }
+}
+
+impl From<engine::StrategyProfile> for StrategyProfile {
+    fn from(p: engine::StrategyProfile) -> Self {
+        StrategyProfile {
+            expansion: p.expansion,
+            aggression: p.aggression,
+            defense: p.defense,
+            saving: p.saving,
+            risk_threshold: p.risk_threshold,
+        }
+    }
+}
+
+impl From<Balance> for engine::Balance {
+    fn from(b: Balance) -> Self {
+        engine::Balance {
+            initial_gold: b.initial_gold,
+            initial_wood: b.initial_wood,
+            unit_cost_gold: b.unit_cost_gold,
+            defense_cost_wood: b.defense_cost_wood,
+            resource_per_tick: b.resource_per_tick,
+            max_units: b.max_units,
+        }
+    }
+}
+
+impl From<engine::Balance> for Balance {
+    fn from(b: engine::Balance) -> Self {
+        Balance {
+            initial_gold: b.initial_gold,
+            initial_wood: b.initial_wood,
+            unit_cost_gold: b.unit_cost_gold,
+            defense_cost_wood: b.defense_cost_wood,
+            resource_per_tick: b.resource_per_tick,
+            max_units: b.max_units,
+        }
+    }
+}
+
+impl From<GameMode> for engine::GameMode {
+    fn from(mode: GameMode) -> Self {
+        match mode {
+            GameMode::Standard => engine::GameMode::Standard,
+            GameMode::BattleRoyale { shrink_interval_slots } => engine::GameMode::BattleRoyale { shrink_interval_slots },
+            GameMode::CaptureTheFlag { captures_to_win } => engine::GameMode::CaptureTheFlag { captures_to_win },
+        }
+    }
 }
 
-#[derive(Accounts)]
-#[instruction(game_id: u64)]
-pub struct SetStrategy<'info> {
-    #[account(mut, seeds = [PLAYER_SEED, &game_id.to_le_bytes(), player.key().as_ref()], bump)]
-    pub player_state: Account<'info, PlayerState>,
-    #[account(mut)]
-    pub player: Signer<'info>,
+impl From<VictoryCondition> for engine::VictoryCondition {
+    fn from(condition: VictoryCondition) -> Self {
+        match condition {
+            VictoryCondition::Conquest => engine::VictoryCondition::Conquest,
+            VictoryCondition::Domination { percent, ticks } => engine::VictoryCondition::Domination { percent, ticks },
+            VictoryCondition::ScoreRace { target } => engine::VictoryCondition::ScoreRace { target },
+            VictoryCondition::Timed { deadline } => engine::VictoryCondition::Timed { deadline },
+            VictoryCondition::KingOfTheHill { ticks } => engine::VictoryCondition::KingOfTheHill { ticks },
+        }
+    }
 }
 
-#[derive(Accounts)]
-pub struct EndGame<'info> {
-    #[account(mut)]
-    pub game: Account<'info, Game>,
-    pub authority: Signer<'info>,
+impl From<engine::MoveOutcome> for MoveOutcome {
+    fn from(outcome: engine::MoveOutcome) -> Self {
+        match outcome {
+            engine::MoveOutcome::Claimed => MoveOutcome::Claimed,
+            engine::MoveOutcome::Reinforced => MoveOutcome::Reinforced,
+            engine::MoveOutcome::Won { defender, attackers_left } => MoveOutcome::Won { defender, attackers_left },
+            engine::MoveOutcome::Repelled { defenders_left } => MoveOutcome::Repelled { defenders_left },
+            engine::MoveOutcome::CampCaptured { attackers_left, reward } => {
+                MoveOutcome::CampCaptured { attackers_left, reward: reward.into() }
+            }
+            engine::MoveOutcome::ResourceClaimed { resource_type, amount } => {
+                MoveOutcome::ResourceClaimed { resource_type: resource_type.into(), amount }
+            }
+        }
+    }
 }
 
-#[derive(Accounts)]
-pub struct DelegateGame<'info> {
-    #[account(mut)]
-    pub payer: Signer<'info>,
-    #[account(mut)]
-    pub game: Account<'info, Game>,
-    /// CHECK: Delegation program
-    #[account(address = DELEGATION_PROGRAM_ID)]
-    pub delegation_program: AccountInfo<'info>,
-    /// CHECK: Buffer
-    #[account(mut)]
+// ==================== EPHEMERAL ROLLUPS ====================
+
+/// Accounts needed to hand one of our PDAs over to the delegation program
+pub struct DelegationAccounts<'info> {
+    pub payer: AccountInfo<'info>,
+    pub pda: AccountInfo<'info>,
+    pub owner_program: AccountInfo<'info>,
     pub buffer: AccountInfo<'info>,
-    /// CHECK: Delegation record
-    #[account(mut)]
     pub delegation_record: AccountInfo<'info>,
-    /// CHECK: Delegation metadata
-    #[account(mut)]
     pub delegation_metadata: AccountInfo<'info>,
-    pub system_program: Program<'info, System>,
-    /// CHECK: Owner program
-    #[account(address = crate::ID)]
-    pub owner_program: AccountInfo<'info>,
+    pub delegation_program: AccountInfo<'info>,
+    pub system_program: AccountInfo<'info>,
 }
 
-// ==================== STATE ====================
+#[derive(AnchorSerialize)]
+struct DelegateAccountArgs {
+    commit_frequency_ms: u32,
+    seeds: Vec<Vec<u8>>,
+    validator: Option<Pubkey>,
+}
 
-#[account]
-pub struct Game {
+impl<'info> DelegationAccounts<'info> {
+    /// Park the PDA's data in a buffer, hand ownership to the delegation program
+    /// and let it register the delegation
+    pub fn delegate(&self, pda_seeds: &[&[u8]], bump: u8, commit_frequency_ms: u32) -> Result<()> {
+        let (buffer, buffer_bump) =
+            Pubkey::find_program_address(&[BUFFER_SEED, self.pda.key.as_ref()], &crate::ID);
+        let (record, _) = Pubkey::find_program_address(
+            &[DELEGATION_RECORD_SEED, self.pda.key.as_ref()],
+            &DELEGATION_PROGRAM_ID,
+        );
+        let (metadata, _) = Pubkey::find_program_address(
+            &[DELEGATION_METADATA_SEED, self.pda.key.as_ref()],
+            &DELEGATION_PROGRAM_ID,
+        );
+        require_keys_eq!(self.buffer.key(), buffer, GameError::InvalidDelegationAccounts);
+        require_keys_eq!(self.delegation_record.key(), record, GameError::InvalidDelegationAccounts);
+        require_keys_eq!(self.delegation_metadata.key(), metadata, GameError::InvalidDelegationAccounts);
+
+        let buffer_seeds: &[&[u8]] = &[BUFFER_SEED, self.pda.key.as_ref(), &[buffer_bump]];
+        let bump_seed = [bump];
+        let mut pda_signer_seeds = pda_seeds.to_vec();
+        pda_signer_seeds.push(&bump_seed);
+
+        // Copy the data into a temporary buffer
+        let data_len = self.pda.data_len();
+        anchor_lang::system_program::create_account(
+            CpiContext::new_with_signer(
+                self.system_program.clone(),
+                anchor_lang::system_program::CreateAccount {
+                    from: self.payer.clone(),
+                    to: self.buffer.clone(),
+                },
+                &[buffer_seeds],
+            ),
+            Rent::get()?.minimum_balance(data_len),
+            data_len as u64,
+            &crate::ID,
+        )?;
+        self.buffer.try_borrow_mut_data()?.copy_from_slice(&self.pda.try_borrow_data()?);
+
+        // Ownership can only change once the data is zeroed
+        self.pda.try_borrow_mut_data()?.fill(0);
+        self.pda.assign(&DELEGATION_PROGRAM_ID);
+
+        let mut data = DELEGATE_DISCRIMINATOR.to_vec();
+        DelegateAccountArgs {
+            commit_frequency_ms,
+            seeds: pda_seeds.iter().map(|seed| seed.to_vec()).collect(),
+            validator: None,
+        }
+        .serialize(&mut data)?;
+
+        let ix = anchor_lang::solana_program::instruction::Instruction {
+            program_id: DELEGATION_PROGRAM_ID,
+            accounts: vec![
+                anchor_lang::solana_program::instruction::AccountMeta::new(self.payer.key(), true),
+                anchor_lang::solana_program::instruction::AccountMeta::new(self.pda.key(), true),
+                anchor_lang::solana_program::instruction::AccountMeta::new_readonly(self.owner_program.key(), false),
+                anchor_lang::solana_program::instruction::AccountMeta::new(self.buffer.key(), false),
+                anchor_lang::solana_program::instruction::AccountMeta::new(self.delegation_record.key(), false),
+                anchor_lang::solana_program::instruction::AccountMeta::new(self.delegation_metadata.key(), false),
+                anchor_lang::solana_program::instruction::AccountMeta::new_readonly(self.system_program.key(), false),
+            ],
+            data,
+        };
+
+        anchor_lang::solana_program::program::invoke_signed(
+            &ix,
+            &[
+                self.payer.clone(),
+                self.pda.clone(),
+                self.owner_program.clone(),
+                self.buffer.clone(),
+                self.delegation_record.clone(),
+                self.delegation_metadata.clone(),
+                self.system_program.clone(),
+                self.delegation_program.clone(),
+            ],
+            &[&pda_signer_seeds],
+        )?;
+
+        // Close the buffer, refunding the payer
+        let lamports = self.buffer.lamports();
+        **self.buffer.try_borrow_mut_lamports()? = 0;
+        **self.payer.try_borrow_mut_lamports()? += lamports;
+        self.buffer.resize(0)?;
+        self.buffer.assign(&anchor_lang::system_program::ID);
+        Ok(())
+    }
+}
+
+/// Ask the ER to commit `game` and `players` to the base layer, optionally undelegating them
+pub fn schedule_commit<'info>(
+    payer: &AccountInfo<'info>,
+    magic_context: &AccountInfo<'info>,
+    magic_program: &AccountInfo<'info>,
+    game: &AccountInfo<'info>,
+    players: &[AccountInfo<'info>],
+    undelegate: bool,
+) -> Result<()> {
+    let mut accounts = vec![
+        anchor_lang::solana_program::instruction::AccountMeta::new(payer.key(), true),
+        anchor_lang::solana_program::instruction::AccountMeta::new(magic_context.key(), false),
+        anchor_lang::solana_program::instruction::AccountMeta::new(game.key(), false),
+    ];
+    let mut infos = vec![payer.clone(), magic_context.clone(), game.clone()];
+    for player in players {
+        accounts.push(anchor_lang::solana_program::instruction::AccountMeta::new(player.key(), false));
+        infos.push(player.clone());
+    }
+    infos.push(magic_program.clone());
+
+    let ix = anchor_lang::solana_program::instruction::Instruction {
+        program_id: MAGIC_PROGRAM_ID,
+        accounts,
+        data: if undelegate {
+            SCHEDULE_COMMIT_AND_UNDELEGATE_DISCRIMINATOR.to_vec()
+        } else {
+            SCHEDULE_COMMIT_DISCRIMINATOR.to_vec()
+        },
+    };
+    anchor_lang::solana_program::program::invoke(&ix, &infos)?;
+    Ok(())
+}
+
+// ==================== EVENTS ====================
+
+#[event]
+pub struct GameCreated {
     pub game_id: u64,
     pub creator: Pubkey,
     pub stake_amount: u64,
-    pub player_count: u8,
-    pub status: GameStatus,
-    pub turn: u64,
-    pub winner: Option<Pubkey>,
-    pub grid: [[TileState; 8]; 8],
+    pub team_layout: TeamLayout,
+    pub victory_condition: VictoryCondition,
+    pub game_mode: GameMode,
+    pub config_version: u32,
     pub created_at: i64,
+}
+
+#[event]
+pub struct PlayerJoined {
+    pub game_id: u64,
+    pub player: Pubkey,
+    pub player_index: u8,
+    pub team: u8,
+}
+
+#[event]
+pub struct GameStarted {
+    pub game_id: u64,
+    pub player_count: u8,
     pub started_at: i64,
-    pub finished_at: i64,
 }
 
-impl Game {
-    pub const LEN: usize = 8 + 32 + 8 + 1 + 1 + 8 + (1 + 32) + (64 * 36) + 8 + 8 + 8 + 256;
+#[event]
+pub struct UnitsMoved {
+    pub game_id: u64,
+    pub player_index: u8,
+    pub from_x: u8,
+    pub from_y: u8,
+    pub to_x: u8,
+    pub to_y: u8,
+    pub unit_count: u8,
+    pub outcome: MoveOutcome,
+    /// Game turn after the move
+    pub turn: u64,
 }
 
-#[account]
-pub struct PlayerState {
+/// Emitted alongside `UnitsMoved` whenever the destination changes hands
+#[event]
+pub struct TileCaptured {
+    pub game_id: u64,
+    pub player_index: u8,
+    pub x: u8,
+    pub y: u8,
+    /// None when taken from empty land, a resource or a neutral camp
+    pub previous_owner: Option<u8>,
+}
+
+#[event]
+pub struct DefenseBuilt {
+    pub game_id: u64,
+    pub player_index: u8,
+    pub x: u8,
+    pub y: u8,
+}
+
+#[event]
+pub struct UnitsTrained {
+    pub game_id: u64,
+    pub player_index: u8,
+    pub x: u8,
+    pub y: u8,
+    pub count: u8,
+    pub gold_spent: u64,
+}
+
+#[event]
+pub struct ResourcesCollected {
     pub game_id: u64,
-    pub player: Pubkey,
     pub player_index: u8,
     pub gold: u64,
     pub wood: u64,
-    pub units: u8,
-    pub score: u64,
-    pub is_alive: bool,
-    pub strategy_mode: StrategyMode,
 }
 
-impl PlayerState {
-    pub const LEN: usize = 8 + 32 + 1 + 8 + 8 + 1 + 8 + 1 + 1 + 64;
+#[event]
+pub struct StrategyChanged {
+    pub game_id: u64,
+    pub player_index: u8,
+    pub mode: StrategyMode,
+    /// Profile now in effect
+    pub profile: StrategyProfile,
+    /// Whether `profile` is a custom one rather than the mode preset
+    pub custom: bool,
+}
+
+#[event]
+pub struct PlayerEliminated {
+    pub game_id: u64,
+    pub player_index: u8,
+}
+
+#[event]
+pub struct GameFinished {
+    pub game_id: u64,
+    /// None on a draw
+    pub winning_team: Option<u8>,
+    pub winner: Option<Pubkey>,
+    pub pot: u64,
+    /// Protocol fee taken from the pot
+    pub fee: u64,
+    pub turn: u64,
+    pub finished_at: i64,
+}
+
+#[event]
+pub struct GameDelegated {
+    pub game_id: u64,
+    pub commit_frequency_ms: u32,
+    pub player_count: u8,
+}
+
+#[event]
+pub struct GameRefunded {
+    pub game_id: u64,
+    pub player_count: u8,
+    pub stake_amount: u64,
+}
+
+#[event]
+pub struct AdminChanged {
+    pub previous_admin: Pubkey,
+    pub new_admin: Pubkey,
+}
+
+#[event]
+pub struct ProgramPauseChanged {
+    pub paused: bool,
+}
+
+#[event]
+pub struct RatingChanged {
+    pub game_id: u64,
+    pub player: Pubkey,
+    pub rating: u32,
+    pub delta: i32,
+}
+
+#[event]
+pub struct MatchFormed {
+    pub queue_id: u32,
+    pub game_id: u64,
+    pub players: Vec<Pubkey>,
+}
+
+#[event]
+pub struct SeasonClosed {
+    pub season_id: u32,
+    pub leader: Option<Pubkey>,
+    pub prizes_paid: u64,
+}
+
+#[event]
+pub struct TreasuryWithdrawn {
+    pub amount: u64,
+    pub recipient: Pubkey,
 }
 
 // ==================== ENUMS ====================
@@ -583,6 +4227,20 @@ pub enum TileState {
         resource_type: ResourceType,
         amount: u64,
     },
+    /// Swallowed by the battle royale storm, impassable
+    Collapsed,
+    /// Neutral camp guarded by `units`, pays `reward` to whoever takes it
+    Neutral {
+        units: u8,
+        reward: CampReward,
+    },
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CampReward {
+    Gold { amount: u64 },
+    Wood { amount: u64 },
+    Units { count: u8 },
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
@@ -591,6 +4249,138 @@ pub enum ResourceType {
     Wood,
 }
 
+impl ResourceType {
+    pub fn other(self) -> Self {
+        match self {
+            ResourceType::Gold => ResourceType::Wood,
+            ResourceType::Wood => ResourceType::Gold,
+        }
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TeamLayout {
+    FreeForAll,
+    /// Joiners alternate teams; team 0 spawns on the top edge, team 1 on the bottom
+    TwoVsTwo,
+}
+
+impl TeamLayout {
+    pub fn team_of(self, player_index: u8) -> u8 {
+        match self {
+            TeamLayout::FreeForAll => player_index,
+            TeamLayout::TwoVsTwo => player_index % 2,
+        }
+    }
+
+    pub fn spawn_corner(self, player_index: u8) -> (u8, u8) {
+        match (self, player_index) {
+            (TeamLayout::FreeForAll, 0) => (0, 0),
+            (TeamLayout::FreeForAll, 1) => (6, 0),
+            (TeamLayout::FreeForAll, 2) => (0, 6),
+            (TeamLayout::FreeForAll, _) => (6, 6),
+            (TeamLayout::TwoVsTwo, 0) => (0, 0),
+            (TeamLayout::TwoVsTwo, 1) => (6, 6),
+            (TeamLayout::TwoVsTwo, 2) => (6, 0),
+            (TeamLayout::TwoVsTwo, _) => (0, 6),
+        }
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GameMode {
+    Standard,
+    /// The map shrinks by one ring every `shrink_interval_slots` slots
+    BattleRoyale { shrink_interval_slots: u64 },
+    /// Carry an enemy flag back to your own flag's tile; first team to `captures_to_win` wins
+    CaptureTheFlag { captures_to_win: u8 },
+}
+
+impl GameMode {
+    pub const LEN: usize = 1 + 8;
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum VictoryCondition {
+    /// Last team with territory wins
+    Conquest,
+    /// Hold `percent` of all tiles for `ticks` turns
+    Domination { percent: u8, ticks: u64 },
+    /// First team to `target` points
+    ScoreRace { target: u64 },
+    /// Highest score once `deadline` (unix timestamp) passes
+    Timed { deadline: i64 },
+    /// Hold every center resource tile for `ticks` consecutive turns
+    KingOfTheHill { ticks: u64 },
+}
+
+impl VictoryCondition {
+    pub const LEN: usize = 1 + 1 + 8;
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DiplomacyState {
+    War,
+    Peace,
+    Alliance,
+}
+
+/// What happened at the destination of a move
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MoveOutcome {
+    Claimed,
+    Reinforced,
+    Won { defender: u8, attackers_left: u8 },
+    Repelled { defenders_left: u8 },
+    CampCaptured { attackers_left: u8, reward: CampReward },
+    ResourceClaimed { resource_type: ResourceType, amount: u64 },
+}
+
+impl MoveOutcome {
+    /// Compact code stored in `ActionRecord::result`
+    pub fn code(&self) -> u8 {
+        match self {
+            MoveOutcome::Claimed => 0,
+            MoveOutcome::Reinforced => 1,
+            MoveOutcome::Won { .. } => 2,
+            MoveOutcome::Repelled { .. } => 3,
+            MoveOutcome::CampCaptured { .. } => 4,
+            MoveOutcome::ResourceClaimed { .. } => 5,
+        }
+    }
+
+    /// Units left standing on the tile, or the resource amount claimed
+    pub fn value(&self) -> u64 {
+        match *self {
+            MoveOutcome::Claimed | MoveOutcome::Reinforced => 0,
+            MoveOutcome::Won { attackers_left, .. } | MoveOutcome::CampCaptured { attackers_left, .. } => {
+                attackers_left as u64
+            }
+            MoveOutcome::Repelled { defenders_left } => defenders_left as u64,
+            MoveOutcome::ResourceClaimed { amount, .. } => amount,
+        }
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u8)]
+pub enum ActionKind {
+    Move,
+    Build,
+    Train,
+    Collect,
+    Swap,
+    Trade,
+    Treaty,
+    BreakTreaty,
+    Storm,
+    Barbarians,
+    OfferTrade,
+    CancelOffer,
+    ProposeTreaty,
+    Autoplay,
+}
+
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq, Debug)]
 pub enum StrategyMode {
     Aggressive,
@@ -599,6 +4389,13 @@ pub enum StrategyMode {
     Economic,
 }
 
+impl StrategyMode {
+    /// Preset profile used when the player has not set a custom one
+    pub fn profile(&self) -> StrategyProfile {
+        engine::StrategyMode::from(self.clone()).profile().into()
+    }
+}
+
 // ==================== ERRORS ====================
 
 #[error_code]
@@ -629,4 +4426,384 @@ pub enum GameError {
     MaxUnitsReached,
     #[msg("Player has been eliminated")]
     PlayerEliminated,
+    #[msg("Invalid trade offer")]
+    InvalidTradeOffer,
+    #[msg("Cannot trade with yourself")]
+    CannotTradeWithSelf,
+    #[msg("Trade offer has expired")]
+    OfferExpired,
+    #[msg("Trade offer is not for you")]
+    OfferNotForYou,
+    #[msg("Trade offer has not expired")]
+    OfferNotExpired,
+    #[msg("Invalid swap amount")]
+    InvalidSwap,
+    #[msg("Swap output below minimum")]
+    SlippageExceeded,
+    #[msg("Invalid treaty")]
+    InvalidTreaty,
+    #[msg("No pending treaty proposal")]
+    NoTreatyProposal,
+    #[msg("Cannot attack a player you are at peace with")]
+    AtPeace,
+    #[msg("Treaty was broken recently, attacks are on cooldown")]
+    TreatyCooldown,
+    #[msg("Game is not finished")]
+    GameNotFinished,
+    #[msg("Winnings already claimed")]
+    AlreadyClaimed,
+    #[msg("Nothing to claim")]
+    NothingToClaim,
+    #[msg("Invalid victory condition")]
+    InvalidVictoryCondition,
+    #[msg("Victory condition not met")]
+    VictoryConditionNotMet,
+    #[msg("Player accounts do not match the game")]
+    InvalidPlayerAccounts,
+    #[msg("Invalid game mode")]
+    InvalidGameMode,
+    #[msg("Not available in this game mode")]
+    WrongGameMode,
+    #[msg("Storm cannot advance yet")]
+    StormNotReady,
+    #[msg("Storm has fully closed")]
+    StormComplete,
+    #[msg("Tile has collapsed")]
+    TileCollapsed,
+    #[msg("Barbarian wave cannot be sent yet")]
+    WaveNotReady,
+    #[msg("Commit frequency must be positive")]
+    InvalidCommitFrequency,
+    #[msg("Game is not delegated")]
+    NotDelegated,
+    #[msg("Invalid delegation accounts")]
+    InvalidDelegationAccounts,
+    #[msg("Signer is not authorized to act for this player")]
+    Unauthorized,
+    #[msg("Invalid session parameters")]
+    InvalidSession,
+    #[msg("Session has expired")]
+    SessionExpired,
+    #[msg("Action not permitted for this session")]
+    NotPermitted,
+    #[msg("Session spend limit exceeded")]
+    SpendLimitExceeded,
+    #[msg("Invalid agent parameters")]
+    InvalidAgent,
+    #[msg("Agent rate limit reached for this turn")]
+    RateLimited,
+    #[msg("Player has not opted in to autoplay")]
+    AutoplayDisabled,
+    #[msg("Autoplay was run too recently")]
+    AutoplayNotReady,
+    #[msg("This game has an action log that must be passed")]
+    ActionLogRequired,
+    #[msg("Strategy profile needs a non-zero weight and a risk threshold between 100 and 1000")]
+    InvalidStrategyProfile,
+    #[msg("Balance preset needs a non-zero version and unit cost, and room for the starting units")]
+    InvalidBalance,
+    #[msg("This game config has been retired")]
+    GameConfigRetired,
+    #[msg("Only the program admin can do this")]
+    NotAdmin,
+    #[msg("Signer is not the pending admin")]
+    NotPendingAdmin,
+    #[msg("Program is paused")]
+    ProgramPaused,
+    #[msg("Game is already finished")]
+    GameAlreadyFinished,
+    #[msg("Protocol fee exceeds the maximum")]
+    FeeTooHigh,
+    #[msg("Treasury balance too low")]
+    InsufficientTreasury,
+    #[msg("Ratings for this game were already settled")]
+    RatingsAlreadySettled,
+    #[msg("Refunded games are not rated")]
+    GameWasRefunded,
+    #[msg("Season must start before it ends")]
+    InvalidSeason,
+    #[msg("Game did not finish while the season was open")]
+    SeasonNotOpen,
+    #[msg("Season has not ended yet")]
+    SeasonNotEnded,
+    #[msg("Season is already closed")]
+    SeasonClosed,
+    #[msg("Queue needs 2-4 players per match, and 4 for 2v2")]
+    InvalidQueue,
+    #[msg("Queue is full")]
+    QueueFull,
+    #[msg("Player is already queued")]
+    AlreadyQueued,
+    #[msg("Player is not in this queue")]
+    NotQueued,
+    #[msg("Players do not form a valid match for this queue")]
+    InvalidMatch,
+    #[msg("Game IDs from FIRST_ASSIGNED_GAME_ID up are assigned by the game counter")]
+    ReservedGameId,
+    #[msg("Staked games can only end through check_victory or force_refund")]
+    StakedGame,
+    #[msg("A tile cannot hold more than 255 units")]
+    StackFull,
+    #[msg("The treasury must be passed to charge or collect a protocol fee")]
+    TreasuryRequired,
+    #[msg("The game finished in the current season, which must be passed")]
+    SeasonRequired,
+    #[msg("This session or agent has been revoked")]
+    Revoked,
+    #[msg("Only a revoked or expired session can be closed")]
+    SessionStillActive,
+    #[msg("Only a revoked agent can be closed")]
+    AgentStillActive,
+    #[msg("Games formed by a queue only seat the matched players")]
+    MatchedGame,
+}
+
+impl From<engine::EngineError> for GameError {
+    fn from(error: engine::EngineError) -> Self {
+        match error {
+            engine::EngineError::GameNotInLobby => GameError::GameNotInLobby,
+            engine::EngineError::GameFull => GameError::GameFull,
+            engine::EngineError::GameNotActive => GameError::GameNotActive,
+            engine::EngineError::NotEnoughPlayers => GameError::NotEnoughPlayers,
+            engine::EngineError::InvalidPlayer => GameError::InvalidPlayerAccounts,
+            engine::EngineError::PlayerEliminated => GameError::PlayerEliminated,
+            engine::EngineError::OutOfBounds => GameError::OutOfBounds,
+            engine::EngineError::NotAdjacent => GameError::NotAdjacent,
+            engine::EngineError::NotYourTile => GameError::NotYourTile,
+            engine::EngineError::NotEnoughUnits => GameError::NotEnoughUnits,
+            engine::EngineError::NotEnoughResources => GameError::NotEnoughResources,
+            engine::EngineError::AlreadyHasDefense => GameError::AlreadyHasDefense,
+            engine::EngineError::MaxUnitsReached => GameError::MaxUnitsReached,
+            engine::EngineError::AtPeace => GameError::AtPeace,
+            engine::EngineError::TreatyCooldown => GameError::TreatyCooldown,
+            engine::EngineError::TileCollapsed => GameError::TileCollapsed,
+            engine::EngineError::WrongGameMode => GameError::WrongGameMode,
+            engine::EngineError::StormComplete => GameError::StormComplete,
+            engine::EngineError::StackFull => GameError::StackFull,
+        }
+    }
+}
+
+// ==================== TESTS ====================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An all-zero account, as `init` hands it to the handler
+    fn zeroed<T: AccountDeserialize>(len: usize) -> T {
+        T::try_deserialize_unchecked(&mut &vec![0u8; 8 + len][..]).unwrap()
+    }
+
+    fn game(stake_amount: u64, teams: &[u8], fee_bps: u16) -> Game {
+        let mut game: Game = zeroed(Game::LEN);
+        game.init(1, 255, Pubkey::new_unique(), stake_amount, 0);
+        for (i, &team) in teams.iter().enumerate() {
+            game.players[i] = Pubkey::new_unique();
+            game.teams[i] = team;
+        }
+        game.player_count = teams.len() as u8;
+        game.fee_bps = fee_bps;
+        game
+    }
+
+    fn player(team: u8, is_alive: bool, score: u64) -> PlayerState {
+        PlayerState { team, is_alive, score, ..zeroed(PlayerState::LEN) }
+    }
+
+    fn profile(points: u32, wins: u32, rating: u32) -> PlayerProfile {
+        PlayerProfile {
+            player: Pubkey::new_unique(),
+            season_points: points,
+            season_wins: wins,
+            rating,
+            ..zeroed(PlayerProfile::LEN)
+        }
+    }
+
+    #[test]
+    fn fee_rounds_down_and_never_exceeds_the_cap() {
+        let mut small = game(999, &[0, 1, 2], MAX_PROTOCOL_FEE_BPS);
+        small.finish(Some(0), 10);
+        assert_eq!(small.fee, 299, "10% of 2997 rounds down");
+        assert!(small.fee as u128 * BPS_DENOMINATOR as u128 <= small.pot() as u128 * MAX_PROTOCOL_FEE_BPS as u128);
+
+        let mut large = game(u64::MAX / 4, &[0, 1, 2, 3], MAX_PROTOCOL_FEE_BPS);
+        large.finish(None, 10);
+        assert_eq!(large.fee, large.pot() / 10, "no overflow on the largest pots");
+
+        let mut free = game(999, &[0, 1], 0);
+        free.finish(Some(1), 10);
+        assert_eq!(free.fee, 0);
+
+        let mut refunded = game(999, &[0, 1], MAX_PROTOCOL_FEE_BPS);
+        refunded.refunded = true;
+        refunded.finish(None, 10);
+        assert_eq!(refunded.fee, 0);
+        assert_eq!(refunded.payout_for(0), 999);
+        assert_eq!(refunded.payout_for(1), 999);
+    }
+
+    #[test]
+    fn payouts_add_up_to_the_pot_minus_the_fee() {
+        // 2v2 won by team 1: its two members split the prize
+        let mut teams = game(1_000, &[0, 1, 0, 1], 250);
+        teams.finish(Some(1), 10);
+        assert_eq!(teams.fee, 100);
+        assert_eq!(teams.payout_for(0), 0);
+        assert_eq!(teams.payout_for(1) * 2, teams.pot() - teams.fee);
+
+        // Free-for-all win and draw, with rounding dust left in the game
+        let mut ffa = game(1_001, &[0, 1, 2], 300);
+        ffa.finish(Some(2), 10);
+        assert_eq!(ffa.payout_for(2), ffa.pot() - ffa.fee);
+        assert_eq!(ffa.payout_for(0) + ffa.payout_for(1), 0);
+
+        let mut draw = game(1_001, &[0, 1, 2], 300);
+        draw.finish(None, 10);
+        let paid: u64 = (0..3).map(|team| draw.payout_for(team)).sum();
+        let prize = draw.pot() - draw.fee;
+        assert!(paid <= prize && prize - paid < 3, "only rounding dust stays behind");
+        assert_eq!(draw.winner, None);
+    }
+
+    #[test]
+    fn placements_rank_winners_then_survivors_then_score() {
+        let mut won = game(0, &[0, 1, 0, 1], 0);
+        won.winning_team = Some(1);
+        let players = [player(0, true, 50), player(1, false, 10), player(0, true, 5), player(1, true, 1)];
+        let refs: Vec<&PlayerState> = players.iter().collect();
+        assert_eq!(placements(&won, &refs), vec![2, 0, 2, 0], "teammates share a placement");
+
+        let draw = game(0, &[0, 1, 2, 3], 0);
+        let players = [player(0, true, 5), player(1, false, 100), player(2, true, 7), player(3, true, 7)];
+        let refs: Vec<&PlayerState> = players.iter().collect();
+        assert_eq!(placements(&draw, &refs), vec![2, 3, 0, 0]);
+    }
+
+    #[test]
+    fn elo_deltas_are_zero_sum_and_handle_ties() {
+        let deltas = elo_deltas(&[1500, 1500, 1600, 1400], &[0, 1, 2, 3], &[0, 1, 2, 3]);
+        assert!(deltas[0] > 0 && deltas[3] < 0);
+        assert!(deltas.iter().sum::<i32>().abs() <= 2, "only rounding breaks the zero sum: {deltas:?}");
+        assert!(deltas.iter().all(|delta| delta.abs() as f64 <= RATING_K));
+
+        // An upset moves ratings further than the expected result
+        let upset = elo_deltas(&[1200, 1800], &[0, 1], &[0, 1]);
+        let expected = elo_deltas(&[1800, 1200], &[0, 1], &[0, 1]);
+        assert!(upset[0] > expected[0]);
+        assert_eq!(upset[0] + upset[1], 0);
+
+        assert_eq!(elo_deltas(&[1500; 3], &[0; 3], &[0, 1, 2]), vec![0; 3], "an even tie changes nothing");
+        assert_eq!(elo_deltas(&[1500; 4], &[0, 1, 0, 1], &[0, 1, 0, 1]), vec![16, -16, 16, -16]);
+        assert_eq!(elo_deltas(&[1500, 1600], &[0, 1], &[0, 0]), vec![0, 0], "teammates are not opponents");
+    }
+
+    #[test]
+    fn leaderboard_stays_sorted_and_evicts_the_last_rank() {
+        let mut season: Season = zeroed(Season::LEN);
+        let mut profiles: Vec<PlayerProfile> =
+            [4, 9, 1, 7, 3, 10, 2, 8, 6, 5].iter().map(|&points| profile(points, 0, 1500)).collect();
+        for profile in &profiles {
+            season.record(profile);
+        }
+        let points = |season: &Season| season.entries.iter().map(|e| e.points).collect::<Vec<_>>();
+        assert_eq!(season.entry_count as usize, SEASON_BOARD_SIZE);
+        assert_eq!(points(&season), vec![10, 9, 8, 7, 6, 5, 4, 3, 2, 1]);
+
+        // Below the last rank: ignored. Above it: replaces it.
+        season.record(&profile(1, 0, 1400));
+        assert_eq!(points(&season), vec![10, 9, 8, 7, 6, 5, 4, 3, 2, 1]);
+        let newcomer = profile(5, 3, 1500);
+        season.record(&newcomer);
+        assert_eq!(points(&season), vec![10, 9, 8, 7, 6, 5, 5, 4, 3, 2]);
+        assert_eq!(season.entries[5].player, newcomer.player, "wins break the tie on points");
+
+        // A player already on the board is refreshed in place, not duplicated
+        profiles[2].season_points = 20;
+        season.record(&profiles[2]);
+        assert_eq!(season.entry_count as usize, SEASON_BOARD_SIZE);
+        assert_eq!(season.entries[0].player, profiles[2].player);
+        assert_eq!(season.entries.iter().filter(|e| e.player == profiles[2].player).count(), 1);
+    }
+
+    #[test]
+    fn queue_removal_keeps_arrival_order() {
+        let mut queue: MatchQueue = zeroed(MatchQueue::LEN);
+        let players: Vec<Pubkey> = (0..4).map(|_| Pubkey::new_unique()).collect();
+        for (i, player) in players.iter().enumerate() {
+            queue.entries[i] = QueueEntry { player: *player, rating: 1500, enqueued_at: i as i64 };
+        }
+        queue.count = 4;
+
+        queue.remove(1);
+        assert_eq!(queue.count, 3);
+        assert_eq!(queue.position(&players[1]), None);
+        assert_eq!(queue.position(&players[2]), Some(1));
+        assert_eq!(queue.entries[3], QueueEntry::default());
+
+        queue.remove(2);
+        queue.remove(0);
+        assert_eq!(queue.count, 1);
+        assert_eq!(queue.entries[0].player, players[2]);
+        assert_eq!(queue.position(&players[3]), None);
+    }
+
+    #[test]
+    fn market_quote_follows_the_constant_product() {
+        let mut market: Market = zeroed(Market::LEN);
+        market.gold_reserve = 1_000;
+        market.wood_reserve = 4_000;
+        assert_eq!(market.quote(ResourceType::Gold, 100), 363);
+        assert_eq!(market.quote(ResourceType::Wood, 400), 90);
+
+        market.fee_bps = 30;
+        for amount in [1, 10, 100, 1_000, 1_000_000] {
+            for resource_in in [ResourceType::Gold, ResourceType::Wood] {
+                let out = market.quote(resource_in, amount);
+                let (reserve_in, reserve_out) = match resource_in {
+                    ResourceType::Gold => (market.gold_reserve, market.wood_reserve),
+                    ResourceType::Wood => (market.wood_reserve, market.gold_reserve),
+                };
+                assert!(out < reserve_out, "the pool is never drained");
+                assert!(
+                    (reserve_in + amount) as u128 * (reserve_out - out) as u128
+                        >= reserve_in as u128 * reserve_out as u128,
+                    "k never shrinks"
+                );
+            }
+        }
+        assert!(market.quote(ResourceType::Gold, 100) < 363, "the fee reduces the output");
+    }
+
+    #[test]
+    fn session_spend_limits_are_enforced() {
+        let mut session: SessionToken = zeroed(SessionToken::LEN);
+        session.max_gold_spend = 100;
+
+        assert!(session.charge(ResourceType::Gold, 60).is_ok());
+        assert_eq!(session.charge(ResourceType::Gold, 50), Err(GameError::SpendLimitExceeded.into()));
+        assert_eq!(session.gold_spent, 60, "a refused charge spends nothing");
+        assert!(session.charge(ResourceType::Gold, 40).is_ok());
+        assert_eq!(session.charge(ResourceType::Wood, 1), Err(GameError::SpendLimitExceeded.into()));
+        assert!(session.charge(ResourceType::Wood, 0).is_ok());
+    }
+
+    #[test]
+    fn agent_rate_limit_resets_each_turn() {
+        let mut agent: AgentAuthority = zeroed(AgentAuthority::LEN);
+        agent.max_actions_per_turn = 2;
+        agent.turn_slots = 10;
+
+        assert!(agent.record_action(0).is_ok());
+        assert!(agent.record_action(3).is_ok());
+        assert_eq!(agent.record_action(9), Err(GameError::RateLimited.into()));
+        assert!(agent.record_action(10).is_ok());
+        assert_eq!(agent.current_turn, 1);
+        assert_eq!(agent.actions_this_turn, 1);
+
+        agent.max_actions_per_turn = 0;
+        agent.turn_slots = 0;
+        assert!((0..100).all(|_| agent.record_action(10).is_ok()), "0 means unlimited");
+    }
 }